@@ -0,0 +1,70 @@
+pub mod environment_light;
+pub mod sky;
+
+use std::sync::Arc;
+use crate::basic::{Color, color, Ray, Vec, white};
+use crate::hittable::sphere_uv;
+use crate::texture::image::{Image, image_texture_filtered, ImageTexture, Filter, Wrap};
+use crate::texture::Texture;
+
+/// Radiance arriving along rays that leave the scene.
+pub trait Background {
+    fn value(&self, r: &Ray) -> Color;
+    /// A direction drawn proportionally to the incoming light, None if the background cannot be
+    /// importance sampled.
+    fn sample_direction(&self) -> Option<Vec> {
+        None
+    }
+    /// Solid angle density of sample_direction() generating direction.
+    fn pdf_value(&self, _direction: &Vec) -> f64 {
+        0.0
+    }
+}
+
+pub struct SolidBackground {
+    color: Color
+}
+
+/// A constant background, use black for interior scenes lit only by their lights.
+pub fn solid_background(color: Color) -> SolidBackground { SolidBackground{ color } }
+
+impl Background for SolidBackground {
+    fn value(&self, _r: &Ray) -> Color { self.color }
+}
+
+/// A vertical blend from `bottom` (looking straight down) to `top` (looking straight up).
+pub struct GradientBackground {
+    bottom: Color,
+    top: Color
+}
+
+pub fn gradient_background(bottom: Color, top: Color) -> GradientBackground { GradientBackground{ bottom, top } }
+
+/// The white to light blue sky used by default.
+pub fn sky_background() -> GradientBackground { gradient_background(white(), color(0.5, 0.7, 1.0)) }
+
+impl Background for GradientBackground {
+    fn value(&self, r: &Ray) -> Color {
+        let unit_direction = r.direction().unit();
+        let a = (unit_direction.y() + 1.0) / 2.0;
+        (1.0 - a) * self.bottom + a * self.top
+    }
+}
+
+/// An equirectangular (latitude-longitude) image surrounding the scene, using the same
+/// orientation as the UVs of a sphere.
+pub struct EnvironmentMap {
+    texture: ImageTexture
+}
+
+pub fn environment_map(image: Arc<Image>) -> EnvironmentMap {
+    EnvironmentMap { texture: image_texture_filtered(image, Filter::Bilinear, Wrap::Clamp) }
+}
+
+impl Background for EnvironmentMap {
+    fn value(&self, r: &Ray) -> Color {
+        let direction = r.direction().unit();
+        let (u, v) = sphere_uv(&direction);
+        self.texture.value(u, v, &direction)
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use crate::basic::*;
+use crate::constants::{PI, random_double};
+use crate::texture::image::Image;
+use super::Background;
+
+/// An equirectangular HDR image used as a light at infinity. Directions are importance sampled
+/// proportionally to the luminance of the image, so small bright regions such as the sun are
+/// found by diffuse bounces instead of showing up as fireflies.
+pub struct EnvironmentLight {
+    image: Arc<Image>,
+    intensity: f64,
+    rotation: f64, // Around the Y axis, in radians
+    distribution: Distribution2D
+}
+
+/// `rotation` turns the map around the vertical axis (in degrees), `intensity` scales its radiance.
+pub fn environment_light(image: Arc<Image>, rotation: f64, intensity: f64) -> EnvironmentLight {
+    let (width, height) = (image.width(), image.height());
+    let mut func = vec![0.0; width * height];
+    for y in 0..height {
+        // Rows near the poles cover less solid angle.
+        let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+        for x in 0..width {
+            func[y * width + x] = image.pixel(x, y).luminance() * sin_theta;
+        }
+    }
+    EnvironmentLight { distribution: distribution_2d(&func, width, height), image, intensity, rotation: rotation.to_radians() }
+}
+
+fn rotate_y(v: &Vec, angle: f64) -> Vec {
+    let (sin, cos) = angle.sin_cos();
+    vec(cos * v.x() + sin * v.z(), v.y(), -sin * v.x() + cos * v.z())
+}
+
+impl EnvironmentLight {
+    /// Image coordinates in [0, 1)^2 (x to the right, y down) of a world direction.
+    fn direction_to_image(&self, direction: &Vec) -> (f64, f64) {
+        let d = rotate_y(&direction.unit(), -self.rotation);
+        let theta = d.y().clamp(-1.0, 1.0).acos(); // Angle from +Y
+        let phi = (-d.z()).atan2(d.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+    fn image_to_direction(&self, x: f64, y: f64) -> Vec {
+        let theta = y * PI;
+        let phi = x * 2.0 * PI - PI;
+        let d = vec(theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin());
+        rotate_y(&d, self.rotation)
+    }
+    fn lookup(&self, x: f64, y: f64) -> Color {
+        if self.image.width() == 0 || self.image.height() == 0 {
+            return black();
+        }
+        let px = ((x * self.image.width() as f64) as usize).min(self.image.width() - 1);
+        let py = ((y * self.image.height() as f64) as usize).min(self.image.height() - 1);
+        self.intensity * self.image.pixel(px, py)
+    }
+}
+
+impl Background for EnvironmentLight {
+    fn value(&self, r: &Ray) -> Color {
+        let (x, y) = self.direction_to_image(r.direction());
+        self.lookup(x, y)
+    }
+    fn sample_direction(&self) -> Option<Vec> {
+        let (x, y, _) = self.distribution.sample_continuous(random_double(), random_double());
+        Some(self.image_to_direction(x, y))
+    }
+    fn pdf_value(&self, direction: &Vec) -> f64 {
+        let (x, y) = self.direction_to_image(direction);
+        let sin_theta = (y * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // Change of variables from the unit square to solid angle.
+        self.distribution.pdf(x, y) / (2.0 * PI * PI * sin_theta)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::basic::*;
+use crate::constants::{PI, random_double};
+use super::Background;
+
+const SUN_ANGULAR_RADIUS: f64 = 0.004652; // Radians, about 0.2665 degrees
+const SUN_ILLUMINANCE: f64 = 128.0; // Extraterrestrial illuminance, in klux
+
+/// Perez luminance distribution F(theta, gamma), theta measured from the zenith and gamma from the sun.
+fn perez(coeffs: &[f64; 5], cos_theta: f64, gamma: f64, cos_gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coeffs;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    color(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z
+    )
+}
+
+/// The analytic daylight model of Preetham et al. (1999) with a sun disk. Luminance is in kcd/m^2
+/// scaled by `intensity`. The model ends at the horizon, below it is an infinite Lambertian ground
+/// lit by the sky and the sun, black unless given an albedo with set_ground_albedo.
+pub struct PreethamSky {
+    sun_direction: Vec,
+    intensity: f64,
+    zenith: [f64; 3], // Y, x, y at the zenith
+    perez_y: [f64; 5],
+    perez_x_chroma: [f64; 5],
+    perez_y_chroma: [f64; 5],
+    normalization: [f64; 3], // F(0, theta_s) for each channel
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    horizon_irradiance: Color, // Falling on a horizontal plane from the sky and the sun
+    ground_albedo: Color
+}
+
+/// A clear sky with the sun in `sun_direction` (pointing towards the sun). `turbidity` ranges from
+/// 2 (very clear) to about 10 (hazy).
+pub fn preetham_sky(sun_direction: Vec, turbidity: f64, intensity: f64) -> PreethamSky {
+    let sun_direction = sun_direction.unit();
+    let t = turbidity;
+    // Keep the sun slightly above the horizon, the fit is not valid below it.
+    let theta_s = sun_direction.y().clamp(0.0, 1.0).acos().min(PI / 2.0 - 0.01);
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let theta = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+    let chroma = |m: [[f64; 4]; 3]| -> f64 {
+        let row = |r: [f64; 4]| r.iter().zip(theta.iter()).map(|(a, b)| a * b).sum::<f64>();
+        t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+    };
+    let zenith_x = chroma([[0.00166, -0.00375, 0.00209, 0.0], [-0.02903, 0.06377, -0.03202, 0.00394], [0.11693, -0.21196, 0.06052, 0.25886]]);
+    let zenith_y = chroma([[0.00275, -0.00610, 0.00317, 0.0], [-0.04214, 0.08970, -0.04153, 0.00516], [0.15346, -0.26756, 0.06670, 0.26688]]);
+
+    let perez_y = [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703];
+    let perez_x_chroma = [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452];
+    let perez_y_chroma = [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529];
+    let normalization = [
+        perez(&perez_y, 1.0, theta_s, theta_s.cos()),
+        perez(&perez_x_chroma, 1.0, theta_s, theta_s.cos()),
+        perez(&perez_y_chroma, 1.0, theta_s, theta_s.cos())
+    ];
+
+    let mut ret = PreethamSky {
+        sun_direction,
+        intensity,
+        zenith: [zenith_luminance, zenith_x, zenith_y],
+        perez_y,
+        perez_x_chroma,
+        perez_y_chroma,
+        normalization,
+        sun_radiance: sun_radiance(theta_s, t),
+        cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+        horizon_irradiance: black(),
+        ground_albedo: black()
+    };
+    ret.horizon_irradiance = ret.horizontal_irradiance();
+    ret
+}
+
+/// The sky for a given day of the year (1 to 365), local solar time in hours and latitude in
+/// degrees. +Y points up, -Z north and +X east.
+pub fn preetham_sky_at(day_of_year: i32, solar_time: f64, latitude: f64, turbidity: f64, intensity: f64) -> PreethamSky {
+    preetham_sky(sun_direction_at(day_of_year, solar_time, latitude), turbidity, intensity)
+}
+
+/// Direction towards the sun from the usual declination and hour angle approximations.
+pub fn sun_direction_at(day_of_year: i32, solar_time: f64, latitude: f64) -> Vec {
+    let declination = (23.45f64).to_radians() * (2.0 * PI * (284 + day_of_year) as f64 / 365.0).sin();
+    let hour_angle = (15.0 * (solar_time - 12.0)).to_radians();
+    let latitude = latitude.to_radians();
+    let sin_elevation = latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    let elevation = sin_elevation.clamp(-1.0, 1.0).asin();
+    // Azimuth measured clockwise from north.
+    let cos_azimuth = ((declination.sin() - sin_elevation * latitude.sin()) / (elevation.cos() * latitude.cos())).clamp(-1.0, 1.0);
+    let mut azimuth = cos_azimuth.acos();
+    if hour_angle > 0.0 {
+        azimuth = 2.0 * PI - azimuth; // Afternoon, the sun is in the west
+    }
+    vec(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos())
+}
+
+/// Radiance of the sun disk after Rayleigh and aerosol extinction, evaluated at one representative
+/// wavelength per channel.
+fn sun_radiance(theta_s: f64, turbidity: f64) -> Color {
+    let relative_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |lambda: f64| -> f64 { // Wavelength in micrometers
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * relative_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * relative_mass).exp();
+        rayleigh * aerosol
+    };
+    let solid_angle = 2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos());
+    SUN_ILLUMINANCE / solid_angle * color(transmittance(0.68), transmittance(0.55), transmittance(0.44))
+}
+
+impl PreethamSky {
+    pub fn sun_direction(&self) -> Vec { self.sun_direction }
+    fn sky(&self, direction: &Vec) -> Color {
+        let cos_theta = direction.y().max(0.01);
+        let cos_gamma = dot(direction, &self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let luminance = self.zenith[0] * perez(&self.perez_y, cos_theta, gamma, cos_gamma) / self.normalization[0];
+        let x = self.zenith[1] * perez(&self.perez_x_chroma, cos_theta, gamma, cos_gamma) / self.normalization[1];
+        let y = self.zenith[2] * perez(&self.perez_y_chroma, cos_theta, gamma, cos_gamma) / self.normalization[2];
+        let rgb = xyz_to_rgb(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+        color(rgb.r().max(0.0), rgb.g().max(0.0), rgb.b().max(0.0))
+    }
+    fn sun_visible(&self) -> bool { self.sun_direction.y() > 0.0 }
+    /// Reflectance of the ground below the horizon, which then shows albedo / pi times the
+    /// irradiance arriving from above. Occlusion by the scene is not taken into account.
+    pub fn set_ground_albedo(&mut self, albedo: Color) {
+        self.ground_albedo = albedo;
+    }
+    /// Cosine weighted integral of the sky over the upper hemisphere by the midpoint rule, plus
+    /// the sun disk.
+    fn horizontal_irradiance(&self) -> Color {
+        const THETA_STEPS: usize = 32;
+        const PHI_STEPS: usize = 64;
+        let (d_theta, d_phi) = (PI / 2.0 / THETA_STEPS as f64, 2.0 * PI / PHI_STEPS as f64);
+        let mut ret = black();
+        for i in 0..THETA_STEPS {
+            let theta = (i as f64 + 0.5) * d_theta;
+            let (sin_theta, cos_theta) = theta.sin_cos();
+            for j in 0..PHI_STEPS {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let direction = vec(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                ret += cos_theta * sin_theta * d_theta * d_phi * self.sky(&direction);
+            }
+        }
+        if self.sun_visible() {
+            ret += 2.0 * PI * (1.0 - self.cos_sun_radius) * self.sun_direction.y() * self.sun_radiance;
+        }
+        ret
+    }
+}
+
+impl Background for PreethamSky {
+    fn value(&self, r: &Ray) -> Color {
+        let direction = r.direction().unit();
+        if direction.y() < 0.0 {
+            return self.intensity / PI * self.ground_albedo * self.horizon_irradiance;
+        }
+        let mut ret = self.sky(&direction);
+        if self.sun_visible() && dot(&direction, &self.sun_direction) >= self.cos_sun_radius {
+            ret += self.sun_radiance;
+        }
+        self.intensity * ret
+    }
+    fn sample_direction(&self) -> Option<Vec> {
+        if !self.sun_visible() || random_double() < 0.5 {
+            return Some(rand_unit_vec());
+        }
+        // Uniform direction in the cone subtended by the sun disk.
+        let cos_theta = 1.0 - random_double() * (1.0 - self.cos_sun_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * random_double();
+        let w = self.sun_direction;
+        let a = if w.x().abs() > 0.9 { vec(0.0, 1.0, 0.0) } else { vec(1.0, 0.0, 0.0) };
+        let u = cross(&w, &a).unit();
+        let v = cross(&w, &u);
+        Some(sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * w)
+    }
+    fn pdf_value(&self, direction: &Vec) -> f64 {
+        let uniform = 1.0 / (4.0 * PI);
+        if !self.sun_visible() {
+            return uniform;
+        }
+        let in_cone = dot(&direction.unit(), &self.sun_direction) >= self.cos_sun_radius;
+        let cone = if in_cone { 1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius)) } else { 0.0 };
+        0.5 * uniform + 0.5 * cone
+    }
+}
@@ -2,10 +2,20 @@ pub mod vec;
 pub mod color;
 pub mod ray;
 pub mod interval;
+pub mod aabb;
+pub mod distribution;
+pub mod transform;
+pub mod quaternion;
+pub mod animation;
 
 pub type Point = Vec;
 
 pub use vec::*;
 pub use color::*;
 pub use ray::*;
-pub use interval::*;
\ No newline at end of file
+pub use interval::*;
+pub use aabb::*;
+pub use distribution::*;
+pub use transform::*;
+pub use quaternion::*;
+pub use animation::*;
\ No newline at end of file
@@ -0,0 +1,93 @@
+use super::*;
+
+/// Axis-aligned bounding box, stored as one interval per axis.
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval
+}
+
+pub fn empty_aabb() -> Aabb {
+    Aabb { x: empty_interval(), y: empty_interval(), z: empty_interval() }
+}
+
+pub fn aabb(x: Interval, y: Interval, z: Interval) -> Aabb {
+    Aabb { x, y, z }
+}
+
+/// The box spanned by two extreme points a and b, in any order.
+pub fn aabb_points(a: Point, b: Point) -> Aabb {
+    Aabb {
+        x: interval(a.x().min(b.x()), a.x().max(b.x())),
+        y: interval(a.y().min(b.y()), a.y().max(b.y())),
+        z: interval(a.z().min(b.z()), a.z().max(b.z()))
+    }
+}
+
+/// The smallest box containing both box0 and box1.
+pub fn aabb_union(box0: &Aabb, box1: &Aabb) -> Aabb {
+    Aabb {
+        x: interval_union(&box0.x, &box1.x),
+        y: interval_union(&box0.y, &box1.y),
+        z: interval_union(&box0.z, &box1.z)
+    }
+}
+
+impl Aabb {
+    pub fn axis(&self, n: i32) -> Interval {
+        if n == 1 {
+            self.y
+        } else if n == 2 {
+            self.z
+        } else {
+            self.x
+        }
+    }
+    pub fn centroid(&self) -> Point {
+        point((self.x.min + self.x.max) / 2.0, (self.y.min + self.y.max) / 2.0, (self.z.min + self.z.max) / 2.0)
+    }
+    /// Surface area of the box, zero for an empty box.
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size();
+        let dy = self.y.size();
+        let dz = self.z.size();
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
+        }
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+    /// Widen any side thinner than delta, so that flat primitives never get a zero-thickness box.
+    pub fn padded(&self, delta: f64) -> Aabb {
+        let pad = |i: Interval| if i.size() < delta { i.expand(delta) } else { i };
+        aabb(pad(self.x), pad(self.y), pad(self.z))
+    }
+    /// Index of the axis along which the box is the largest.
+    pub fn longest_axis(&self) -> i32 {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { 0 } else { 2 }
+        } else if self.y.size() > self.z.size() { 1 } else { 2 }
+    }
+    /// The part of ray_t during which the ray is inside the box.
+    pub fn clip(&self, r: &Ray, ray_t: Interval) -> Option<Interval> {
+        let mut ray_t = ray_t;
+        for a in 0..3 {
+            let inv_d = 1.0 / r.direction()[a];
+            let orig = r.origin()[a];
+            let ax = self.axis(a);
+            let mut t0 = (ax.min - orig) * inv_d;
+            let mut t1 = (ax.max - orig) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > ray_t.min { ray_t.min = t0; }
+            if t1 < ray_t.max { ray_t.max = t1; }
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+        }
+        Some(ray_t)
+    }
+    /// Slab test: whether the ray r overlaps the box anywhere within ray_t.
+    pub fn hit(&self, r: &Ray, ray_t: Interval) -> bool { self.clip(r, ray_t).is_some() }
+}
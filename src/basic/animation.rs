@@ -0,0 +1,124 @@
+use super::*;
+
+/// The pose of an object at one instant: scale first, then rotate, then translate.
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec,
+    pub rotation: Quaternion,
+    pub scale: Vec
+}
+
+pub fn keyframe(time: f64, translation: Vec, rotation: Quaternion, scale: Vec) -> Keyframe {
+    Keyframe { time, translation, rotation, scale }
+}
+
+/// A transform interpolated between keyframes: linearly for translation and scale, with slerp
+/// for the rotation. Before the first and after the last keyframe the pose is held.
+#[derive(Debug, Clone)]
+pub struct AnimatedTransform {
+    keyframes: std::vec::Vec<Keyframe>
+}
+
+pub fn animated_transform(keyframes: std::vec::Vec<Keyframe>) -> AnimatedTransform {
+    assert!(!keyframes.is_empty(), "An animated transform needs at least one keyframe.");
+    let mut keyframes = keyframes;
+    keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    AnimatedTransform { keyframes }
+}
+
+fn keyframe_transform(offset: &Vec, rotation: &Quaternion, scale: &Vec) -> Transform {
+    translation(*offset) * quaternion_rotation(rotation) * scaling(scale.x(), scale.y(), scale.z())
+}
+
+impl AnimatedTransform {
+    pub fn time_range(&self) -> Interval {
+        interval(self.keyframes[0].time, self.keyframes[self.keyframes.len() - 1].time)
+    }
+    pub fn is_animated(&self) -> bool { self.keyframes.len() > 1 }
+    pub fn at(&self, time: f64) -> Transform {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        if time <= first.time {
+            return keyframe_transform(&first.translation, &first.rotation, &first.scale);
+        }
+        if time >= last.time {
+            return keyframe_transform(&last.translation, &last.rotation, &last.scale);
+        }
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let t = if b.time > a.time { (time - a.time) / (b.time - a.time) } else { 0.0 };
+        let translation = (1.0 - t) * a.translation + t * b.translation;
+        let scale = (1.0 - t) * a.scale + t * b.scale;
+        keyframe_transform(&translation, &slerp(&a.rotation, &b.rotation, t), &scale)
+    }
+    /// A box enclosing `b` under every transform of the animation. Each segment is sampled and the
+    /// result padded by how far a rotating corner can bulge out between two samples.
+    pub fn bounds(&self, b: &Aabb) -> Aabb {
+        const STEPS: usize = 64;
+        let mut ret = self.at(self.keyframes[0].time).aabb(b);
+        let mut padding: f64 = 0.0;
+        for pair in self.keyframes.windows(2) {
+            let (k0, k1) = (&pair[0], &pair[1]);
+            for step in 1..=STEPS {
+                let time = k0.time + (k1.time - k0.time) * step as f64 / STEPS as f64;
+                ret = aabb_union(&ret, &self.at(time).aabb(b));
+            }
+            let step_angle = k0.rotation.angle_to(&k1.rotation) / STEPS as f64;
+            let max_scale = k0.scale.x().abs().max(k0.scale.y().abs()).max(k0.scale.z().abs())
+                .max(k1.scale.x().abs().max(k1.scale.y().abs()).max(k1.scale.z().abs()));
+            let radius = max_scale * corner_radius(b);
+            padding = padding.max(radius * (1.0 - (step_angle / 2.0).cos()));
+        }
+        if padding > 0.0 {
+            ret = aabb(ret.x.expand(2.0 * padding), ret.y.expand(2.0 * padding), ret.z.expand(2.0 * padding));
+        }
+        ret
+    }
+}
+
+/// Distance of the farthest box corner from the origin.
+fn corner_radius(b: &Aabb) -> f64 {
+    let x = b.x.min.abs().max(b.x.max.abs());
+    let y = b.y.min.abs().max(b.y.max.abs());
+    let z = b.z.min.abs().max(b.z.max.abs());
+    (x * x + y * y + z * z).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same_transform(a: &Transform, b: &Transform) {
+        for (row_a, row_b) in a.matrix().iter().zip(b.matrix()) {
+            for (x, y) in row_a.iter().zip(row_b) {
+                assert!((x - y).abs() < 1e-9, "{:?} and {:?} differ", a, b);
+            }
+        }
+    }
+
+    fn animation() -> AnimatedTransform {
+        animated_transform(std::vec::Vec::from([
+            keyframe(1.0, vec(4.0, 0.0, 2.0), axis_angle_quaternion(vec(0.0, 0.0, 1.0), 90.0), vec(3.0, 3.0, 3.0)),
+            keyframe(0.0, vec(0.0, 0.0, 0.0), identity_quaternion(), vec(1.0, 1.0, 1.0))
+        ]))
+    }
+
+    #[test]
+    fn keyframes_are_reached_and_held() {
+        let animation = animation();
+        let end = translation(vec(4.0, 0.0, 2.0)) * rotation_z(90.0) * scaling(3.0, 3.0, 3.0);
+        assert_same_transform(&animation.at(0.0), &identity_transform());
+        assert_same_transform(&animation.at(-1.0), &identity_transform());
+        assert_same_transform(&animation.at(1.0), &end);
+        assert_same_transform(&animation.at(2.0), &end);
+    }
+
+    #[test]
+    fn rotation_is_interpolated_at_constant_speed() {
+        let middle = animation().at(0.5);
+        assert_same_transform(&middle, &(translation(vec(2.0, 0.0, 1.0)) * rotation_z(45.0) * scaling(2.0, 2.0, 2.0)));
+        let quarter = animation().at(0.25).vector(&vec(1.0, 0.0, 0.0));
+        assert!((quarter.y().atan2(quarter.x()) - 22.5f64.to_radians()).abs() < 1e-9);
+    }
+}
@@ -15,6 +15,8 @@ impl Color {
     pub fn r(&self) -> f64 { self.r }
     pub fn g(&self) -> f64 { self.g }
     pub fn b(&self) -> f64 { self.b }
+    /// Perceived brightness of a linear RGB color (Rec. 709 weights).
+    pub fn luminance(&self) -> f64 { 0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b }
     pub fn write(&self, output: &mut File, samples_per_pixel: i32) {
         let scale = 1.0 / samples_per_pixel as f64;
 
@@ -32,7 +34,7 @@ impl Color {
         let g = (256.0 * intensity.clamp(g)) as i32;
         let b = (256.0 * intensity.clamp(b)) as i32;
 
-        output.write(format!("{r} {g} {b}\n").as_ref())
+        output.write_all(format!("{r} {g} {b}\n").as_ref())
             .expect("Error occurred when writing image to file.");
     }
 }
@@ -196,6 +198,6 @@ pub fn write_color(output: &mut File, color: &Color) {
     let r = (color.r * 255.999) as i32;
     let g = (color.g * 255.999) as i32;
     let b = (color.b * 255.999) as i32;
-    output.write(format!("{r} {g} {b}\n").as_ref())
+    output.write_all(format!("{r} {g} {b}\n").as_ref())
         .expect("Error occurred when writing image to file.");
 }
\ No newline at end of file
@@ -0,0 +1,108 @@
+/// A piecewise constant 1D distribution over [0, 1) built from non-negative function values.
+/// Without values, or with all of them zero, it is uniform.
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: std::vec::Vec<f64>,
+    cdf: std::vec::Vec<f64>,
+    integral: f64
+}
+
+pub fn distribution_1d(func: std::vec::Vec<f64>) -> Distribution1D {
+    let func = if func.is_empty() { vec![0.0] } else { func };
+    let n = func.len();
+    let mut cdf = vec![0.0; n + 1];
+    for i in 0..n {
+        cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as f64;
+    }
+    let integral = cdf[n];
+    for (i, value) in cdf.iter_mut().enumerate().skip(1) {
+        // An all-zero function falls back to a uniform distribution.
+        *value = if integral > 0.0 { *value / integral } else { i as f64 / n as f64 };
+    }
+    Distribution1D { func, cdf, integral }
+}
+
+impl Distribution1D {
+    pub fn count(&self) -> usize { self.func.len() }
+    pub fn integral(&self) -> f64 { self.integral }
+    /// Map a uniform sample in [0, 1) to a continuous position in [0, 1), returning the position,
+    /// its density and the index of the piece it falls into.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.func.len();
+        // Largest index with cdf[index] <= u.
+        let index = self.cdf.partition_point(|&c| c <= u).saturating_sub(1).min(n - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let du = if width > 0.0 { (u - self.cdf[index]) / width } else { 0.0 };
+        ((index as f64 + du) / n as f64, self.pdf(index), index)
+    }
+    /// Density of the piece at index, with respect to [0, 1).
+    pub fn pdf(&self, index: usize) -> f64 {
+        if self.integral > 0.0 { self.func[index].max(0.0) / self.integral } else { 1.0 }
+    }
+}
+
+/// A piecewise constant 2D distribution over [0, 1)^2, sampled by picking a row from the marginal
+/// and then a column from that row's conditional.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    conditional: std::vec::Vec<Distribution1D>,
+    marginal: Distribution1D
+}
+
+/// Build from `height` rows of `width` values each, stored row by row. An empty grid gives the
+/// uniform distribution.
+pub fn distribution_2d(func: &[f64], width: usize, height: usize) -> Distribution2D {
+    if width == 0 || height == 0 {
+        return distribution_2d(&[0.0], 1, 1);
+    }
+    let conditional: std::vec::Vec<Distribution1D> = (0..height)
+        .map(|y| distribution_1d(func[y * width..(y + 1) * width].to_vec()))
+        .collect();
+    let marginal = distribution_1d(conditional.iter().map(|d| d.integral()).collect());
+    Distribution2D { conditional, marginal }
+}
+
+impl Distribution2D {
+    /// Returns (x, y) in [0, 1)^2 and the density at that point.
+    pub fn sample_continuous(&self, u0: f64, u1: f64) -> (f64, f64, f64) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u1);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u0);
+        (x, y, pdf_x * pdf_y)
+    }
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let height = self.marginal.count();
+        let row = ((y * height as f64) as usize).min(height - 1);
+        let width = self.conditional[row].count();
+        let column = ((x * width as f64) as usize).min(width - 1);
+        self.marginal.pdf(row) * self.conditional[row].pdf(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_and_black_functions_sample_uniformly() {
+        for func in [vec![], vec![0.0; 4]] {
+            let distribution = distribution_1d(func);
+            let (x, pdf, _) = distribution.sample_continuous(0.3);
+            assert!((x - 0.3).abs() < 1e-12);
+            assert_eq!(pdf, 1.0);
+        }
+        let distribution = distribution_2d(&[], 0, 0);
+        let (x, y, pdf) = distribution.sample_continuous(0.25, 0.75);
+        assert!((x - 0.25).abs() < 1e-12 && (y - 0.75).abs() < 1e-12);
+        assert_eq!(pdf, 1.0);
+        assert_eq!(distribution.pdf(0.5, 0.5), 1.0);
+    }
+
+    #[test]
+    fn samples_follow_the_function() {
+        let distribution = distribution_1d(vec![1.0, 3.0]);
+        let (x, pdf, index) = distribution.sample_continuous(0.5);
+        assert_eq!(index, 1);
+        assert!((x - 2.0 / 3.0).abs() < 1e-12);
+        assert!((pdf - 1.5).abs() < 1e-12);
+    }
+}
@@ -27,4 +27,18 @@ impl Interval {
     pub fn clamp(&self, x: f64) -> f64 {
         if x < self.min { self.min } else if x > self.max { self.max } else { x }
     }
-}
\ No newline at end of file
+}
+impl Interval {
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        interval(self.min - padding, self.max + padding)
+    }
+}
+
+/// The smallest interval containing both a and b.
+pub fn interval_union(a: &Interval, b: &Interval) -> Interval {
+    Interval { min: a.min.min(b.min), max: a.max.max(b.max) }
+}
@@ -0,0 +1,80 @@
+use super::*;
+
+/// A rotation quaternion w + xi + yj + zk.
+#[derive(Debug, Copy, Clone)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64
+}
+
+pub fn quaternion(w: f64, x: f64, y: f64, z: f64) -> Quaternion {
+    Quaternion { w, x, y, z }
+}
+
+pub fn identity_quaternion() -> Quaternion {
+    Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 }
+}
+
+/// Rotation by `angle` degrees counter-clockwise around `axis`, matching `rotation`.
+pub fn axis_angle_quaternion(axis: Vec, angle: f64) -> Quaternion {
+    let a = axis.unit();
+    let (sin, cos) = (angle.to_radians() / 2.0).sin_cos();
+    Quaternion { w: cos, x: a.x() * sin, y: a.y() * sin, z: a.z() * sin }
+}
+
+impl Quaternion {
+    pub fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+    pub fn unit(&self) -> Quaternion {
+        let len = self.dot(self).sqrt();
+        Quaternion { w: self.w / len, x: self.x / len, y: self.y / len, z: self.z / len }
+    }
+    /// Angle in radians of the rotation taking self to other, along the shorter way.
+    pub fn angle_to(&self, other: &Quaternion) -> f64 {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+}
+
+/// Spherical linear interpolation along the shorter arc, t = 0 gives a and t = 1 gives b.
+pub fn slerp(a: &Quaternion, b: &Quaternion, t: f64) -> Quaternion {
+    let mut cos_theta = a.dot(b);
+    let mut b = *b;
+    if cos_theta < 0.0 {
+        b = quaternion(-b.w, -b.x, -b.y, -b.z);
+        cos_theta = -cos_theta;
+    }
+    let (wa, wb) = if cos_theta > 0.9995 {
+        // Nearly identical, linear interpolation is accurate and avoids dividing by sin(0).
+        (1.0 - t, t)
+    } else {
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        (((1.0 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+    };
+    quaternion(wa * a.w + wb * b.w, wa * a.x + wb * b.x, wa * a.y + wb * b.y, wa * a.z + wb * b.z).unit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same_rotation(a: &Quaternion, b: &Quaternion) {
+        assert!(a.dot(b).abs() > 1.0 - 1e-12, "{:?} and {:?} differ", a, b);
+    }
+
+    #[test]
+    fn slerp_hits_both_ends_and_halves_the_angle() {
+        let a = axis_angle_quaternion(vec(0.0, 0.0, 1.0), 10.0);
+        let b = axis_angle_quaternion(vec(0.0, 0.0, 1.0), 100.0);
+        assert_same_rotation(&slerp(&a, &b, 0.0), &a);
+        assert_same_rotation(&slerp(&a, &b, 1.0), &b);
+        assert_same_rotation(&slerp(&a, &b, 0.5), &axis_angle_quaternion(vec(0.0, 0.0, 1.0), 55.0));
+        // -b is the same rotation, the interpolation must not take the long way round.
+        let flipped = quaternion(-b.w, -b.x, -b.y, -b.z);
+        assert_same_rotation(&slerp(&a, &flipped, 0.5), &axis_angle_quaternion(vec(0.0, 0.0, 1.0), 55.0));
+        assert!((a.angle_to(&b) - 90.0f64.to_radians()).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,253 @@
+use std::ops::Mul;
+use super::*;
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+/// An affine transformation as a 4x4 matrix, stored together with its inverse so that both
+/// directions and normals are cheap to transform.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    m: Matrix,
+    m_inv: Matrix
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut ret = [[0.0; 4]; 4];
+    for (i, row) in ret.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    ret
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut ret = [[0.0; 4]; 4];
+    for (i, row) in ret.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    ret
+}
+
+/// Gauss-Jordan elimination with partial pivoting, None for a singular matrix.
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let mut a = *m;
+    let mut inv = IDENTITY;
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+        let scale = 1.0 / a[col][col];
+        for k in 0..4 {
+            a[col][k] *= scale;
+            inv[col][k] *= scale;
+        }
+        for row in 0..4 {
+            if row != col {
+                let factor = a[row][col];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[col][k];
+                    inv[row][k] -= factor * inv[col][k];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+pub fn identity_transform() -> Transform {
+    Transform { m: IDENTITY, m_inv: IDENTITY }
+}
+
+/// A transform from an arbitrary row-major matrix, None if it cannot be inverted.
+pub fn matrix_transform(m: Matrix) -> Option<Transform> {
+    Some(Transform { m, m_inv: invert(&m)? })
+}
+
+pub fn translation(offset: Vec) -> Transform {
+    let (x, y, z) = (offset.x(), offset.y(), offset.z());
+    Transform {
+        m: [[1.0, 0.0, 0.0, x], [0.0, 1.0, 0.0, y], [0.0, 0.0, 1.0, z], [0.0, 0.0, 0.0, 1.0]],
+        m_inv: [[1.0, 0.0, 0.0, -x], [0.0, 1.0, 0.0, -y], [0.0, 0.0, 1.0, -z], [0.0, 0.0, 0.0, 1.0]]
+    }
+}
+
+/// Scale by the given factors along each axis, none of which may be zero.
+pub fn scaling(x: f64, y: f64, z: f64) -> Transform {
+    Transform {
+        m: [[x, 0.0, 0.0, 0.0], [0.0, y, 0.0, 0.0], [0.0, 0.0, z, 0.0], [0.0, 0.0, 0.0, 1.0]],
+        m_inv: [[1.0 / x, 0.0, 0.0, 0.0], [0.0, 1.0 / y, 0.0, 0.0], [0.0, 0.0, 1.0 / z, 0.0], [0.0, 0.0, 0.0, 1.0]]
+    }
+}
+
+/// Rotation by `angle` degrees counter-clockwise around `axis` (right-handed).
+pub fn rotation(axis: Vec, angle: f64) -> Transform {
+    let a = axis.unit();
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (x, y, z) = (a.x(), a.y(), a.z());
+    let m = [
+        [x * x + (1.0 - x * x) * cos, x * y * (1.0 - cos) - z * sin, x * z * (1.0 - cos) + y * sin, 0.0],
+        [x * y * (1.0 - cos) + z * sin, y * y + (1.0 - y * y) * cos, y * z * (1.0 - cos) - x * sin, 0.0],
+        [x * z * (1.0 - cos) - y * sin, y * z * (1.0 - cos) + x * sin, z * z + (1.0 - z * z) * cos, 0.0],
+        [0.0, 0.0, 0.0, 1.0]
+    ];
+    // Rotations are orthogonal, the inverse is the transpose.
+    Transform { m, m_inv: transpose(&m) }
+}
+
+/// The rotation described by a unit quaternion.
+pub fn quaternion_rotation(q: &Quaternion) -> Transform {
+    let (w, x, y, z) = (q.w, q.x, q.y, q.z);
+    let m = [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+        [0.0, 0.0, 0.0, 1.0]
+    ];
+    Transform { m, m_inv: transpose(&m) }
+}
+
+/// The change of basis taking the standard axes to u, v, w and the origin to `origin`.
+pub fn frame_transform(u: &Vec, v: &Vec, w: &Vec, origin: &Point) -> Option<Transform> {
+    matrix_transform([
+        [u.x(), v.x(), w.x(), origin.x()],
+        [u.y(), v.y(), w.y(), origin.y()],
+        [u.z(), v.z(), w.z(), origin.z()],
+        [0.0, 0.0, 0.0, 1.0]
+    ])
+}
+
+pub fn rotation_x(angle: f64) -> Transform { rotation(vec(1.0, 0.0, 0.0), angle) }
+pub fn rotation_y(angle: f64) -> Transform { rotation(vec(0.0, 1.0, 0.0), angle) }
+pub fn rotation_z(angle: f64) -> Transform { rotation(vec(0.0, 0.0, 1.0), angle) }
+
+impl Transform {
+    pub fn matrix(&self) -> &Matrix { &self.m }
+    pub fn inverse(&self) -> Transform {
+        Transform { m: self.m_inv, m_inv: self.m }
+    }
+    pub fn point(&self, p: &Point) -> Point {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 { point(x, y, z) } else { point(x / w, y / w, z / w) }
+    }
+    /// Transform a direction, ignoring the translation.
+    pub fn vector(&self, v: &Vec) -> Vec {
+        let m = &self.m;
+        vec(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z()
+        )
+    }
+    /// Transform a surface normal with the inverse transpose, so it stays perpendicular to the
+    /// transformed surface. The result is not normalized.
+    pub fn normal(&self, n: &Vec) -> Vec {
+        let m = &self.m_inv;
+        vec(
+            m[0][0] * n.x() + m[1][0] * n.y() + m[2][0] * n.z(),
+            m[0][1] * n.x() + m[1][1] * n.y() + m[2][1] * n.z(),
+            m[0][2] * n.x() + m[1][2] * n.y() + m[2][2] * n.z()
+        )
+    }
+    /// Determinant of the linear part, the factor by which volumes grow.
+    pub fn determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+    /// Factor by which areas of a surface with normal n grow under the transform.
+    pub fn area_scale(&self, n: &Vec) -> f64 {
+        self.determinant().abs() * self.normal(&n.unit()).length()
+    }
+    /// The direction is not normalized, so ray parameters t are the same on both sides.
+    pub fn ray(&self, r: &Ray) -> Ray {
+        ray(self.point(r.origin()), self.vector(r.direction()), r.time())
+    }
+    /// The box around all eight transformed corners of b.
+    pub fn aabb(&self, b: &Aabb) -> Aabb {
+        let mut ret = empty_aabb();
+        for i in 0..8 {
+            let x = if i & 1 == 0 { b.x.min } else { b.x.max };
+            let y = if i & 2 == 0 { b.y.min } else { b.y.max };
+            let z = if i & 4 == 0 { b.z.min } else { b.z.max };
+            let p = self.point(&point(x, y, z));
+            ret = aabb_union(&ret, &aabb_points(p, p));
+        }
+        ret
+    }
+}
+
+/// Composition: (a * b) applies b first, then a.
+impl Mul for Transform {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Transform { m: multiply(&self.m, &rhs.m), m_inv: multiply(&rhs.m_inv, &self.m_inv) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_identity(m: &Matrix) {
+        for (i, row) in m.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                assert!((value - IDENTITY[i][j]).abs() < 1e-9, "entry ({}, {}) is {}", i, j, value);
+            }
+        }
+    }
+
+    fn transforms() -> [Transform; 3] {
+        [
+            translation(vec(1.0, -2.0, 3.0)) * rotation(vec(1.0, 1.0, 0.0), 40.0) * scaling(2.0, 0.5, 3.0),
+            rotation_x(-70.0) * scaling(-1.0, 4.0, 0.25) * translation(vec(0.0, 5.0, 0.0)),
+            matrix_transform([[2.0, 1.0, 0.0, 1.0], [0.0, 1.0, -1.0, 2.0], [1.0, 0.0, 3.0, -1.0], [0.0, 0.0, 0.0, 1.0]]).unwrap()
+        ]
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        for t in transforms() {
+            assert_identity((t.inverse() * t).matrix());
+            assert_identity((t * t.inverse()).matrix());
+            let p = point(0.3, -1.2, 2.5);
+            assert!((t.inverse().point(&t.point(&p)) - p).length() < 1e-9);
+        }
+        assert!(matrix_transform([[1.0, 2.0, 3.0, 0.0], [2.0, 4.0, 6.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let (a, b) = (vec(1.0, 2.0, 0.5), vec(-0.5, 0.0, 1.0));
+        let n = cross(&a, &b);
+        for t in transforms() {
+            let normal = t.normal(&n).unit();
+            assert!(dot(&normal, &t.vector(&a).unit()).abs() < 1e-9);
+            assert!(dot(&normal, &t.vector(&b).unit()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn area_scale_matches_transformed_parallelograms() {
+        let t = scaling(2.0, 3.0, 4.0);
+        assert!((t.area_scale(&vec(0.0, 0.0, 1.0)) - 6.0).abs() < 1e-9);
+        assert!((t.area_scale(&vec(-5.0, 0.0, 0.0)) - 12.0).abs() < 1e-9);
+        assert!((t.determinant() - 24.0).abs() < 1e-9);
+        let (a, b) = (vec(1.0, 2.0, 0.5), vec(-0.5, 0.0, 1.0));
+        for t in transforms() {
+            let expected = cross(&t.vector(&a), &t.vector(&b)).length() / cross(&a, &b).length();
+            assert!((t.area_scale(&cross(&a, &b)) - expected).abs() < 1e-9 * expected);
+        }
+    }
+}
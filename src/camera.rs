@@ -1,12 +1,27 @@
-use std::fs::File;
 use std::io::Write;
 use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex};
 use std::{io, thread};
 use std::thread::{sleep};
 use super::constants::*;
-use super::hittable::{Hit, HittableList};
+use super::hittable::Hit;
 use super::basic::*;
+use super::background::{Background, sky_background};
+use super::film::{Aov, Film, film, light_group, LightGroup, sample_aovs};
+use super::integrator::{Integrator, path_tracer, RenderStatistics, Scene, splats};
+use super::material::Scatter;
+
+/// What the integrator of cam sees of world.
+fn scene<'a>(cam: &'a Camera, world: &'a (dyn Hit + Send + Sync)) -> Scene<'a> {
+    Scene {
+        camera: cam,
+        world,
+        lights: cam.lights.as_deref(),
+        background: &*cam.background,
+        background_sampled: cam.background_sampled,
+        light_groups: &cam.light_groups
+    }
+}
 
 struct Position {
     i: i32,
@@ -20,7 +35,6 @@ pub struct Camera {
     image_width: i32,
     image_height: i32,
     samples_per_pixel: i32,
-    max_depth: i32,
     vfov: f64,
     focus_dist: f64,
     defocus_angle: f64,
@@ -35,7 +49,28 @@ pub struct Camera {
     vup: Vec,
     u: Vec, // X coordinate: "up" in the camera
     v: Vec, // Y coordinate: "right" in the camera
-    w: Vec // Z coordinate: opposite to the eyesight
+    w: Vec, // Z coordinate: opposite to the eyesight
+    background: Arc<dyn Background + Sync + Send>,
+    background_sampled: bool,
+    lights: Option<Arc<dyn Hit + Send + Sync>>,
+    integrator: Arc<dyn Integrator + Send + Sync>,
+    aovs: std::vec::Vec<Aov>,
+    light_groups: std::vec::Vec<LightGroup>,
+    shutter_open: f64,
+    shutter_close: f64,
+    motion: Option<AnimatedTransform>, // Camera-space keyframes relative to the initial pose
+    camera_to_world: Transform,
+    film_area: f64, // Area of the viewport scaled to unit distance from the lens
+    lens_radius: f64
+}
+
+/// A point on the lens seen from a scene point, for paths traced from the lights.
+pub struct LensConnection {
+    pub pixel: (i32, i32),
+    pub lens: Point,
+    pub importance: f64, // We, the camera's response to light arriving at lens from the point
+    pub pdf: f64, // Area density of the lens point, 1 for a pinhole
+    pub cos_theta: f64 // Between the connection and the viewing direction
 }
 
 pub fn camera() -> Camera {
@@ -44,7 +79,6 @@ pub fn camera() -> Camera {
         image_height: 0,
         image_width: IMAGE_WIDTH,
         samples_per_pixel: SAMPLES_PER_PIXEL,
-        max_depth: MAX_DEPTH,
         vfov: VFOV,
         focus_dist: FOCUS_DIST,
         defocus_angle: DEFOCUS_ANGLE,
@@ -59,51 +93,61 @@ pub fn camera() -> Camera {
         vup: VUP,
         u: empty_vec(),
         v: empty_vec(),
-        w: empty_vec()
+        w: empty_vec(),
+        background: Arc::new(sky_background()),
+        background_sampled: false,
+        lights: None,
+        integrator: Arc::new(path_tracer()),
+        aovs: std::vec::Vec::new(),
+        light_groups: std::vec::Vec::new(),
+        shutter_open: SHUTTER_OPEN,
+        shutter_close: SHUTTER_CLOSE,
+        motion: None,
+        camera_to_world: identity_transform(),
+        film_area: 0.0,
+        lens_radius: 0.0
     };
     ret.initialize();
     ret
 }
 
-fn ray_color(cam: Arc<Camera>, r: &Ray, depth: i32, world: Arc<HittableList>) -> Color {
-    if depth <= 0 {
-        return black();
-    }
-    if let Some(hit_record) = world.hit(r, interval(0.001, INFINITY)) {
-        if let Some(scatter_record) = (*hit_record.mat).scatter(r, &hit_record) {
-            scatter_record.attenuation * ray_color(cam, &scatter_record.scattered, depth - 1, world)
-        } else {
-            black()
-        }
-    } else {
-        let unit_direction = r.direction().unit();
-        let a = (unit_direction.y() + 1.0) / 2.0;
-        (1.0 - a) * white() + a * color(0.5, 0.7, 1.0)
-    }
-}
-
-fn pixel_sample_square(cam: Arc<Camera>) -> Vec {
+fn pixel_sample_square(cam: &Camera) -> Vec {
     let px = -0.5 + random_double();
     let py = -0.5 + random_double();
     px * cam.pixel_delta_u + py * cam.pixel_delta_v
 }
 
-fn defocus_disk_sample(cam: Arc<Camera>) -> Vec {
+fn defocus_disk_sample(cam: &Camera) -> Vec {
     let p = rand_in_unit_disk();
     cam.center + p.x() * cam.defocus_dist_u + p.y() * cam.defocus_dist_v
 }
 
-fn get_ray(cam: Arc<Camera>, i: i32, j: i32) -> Ray {
+pub fn get_ray(cam: &Camera, i: i32, j: i32) -> Ray {
     let pixel_center = cam.pixel00_loc + i as f64 * cam.pixel_delta_u + j as f64 * cam.pixel_delta_v;
-    let pixel_sample = pixel_center + pixel_sample_square(cam.clone());
-    let ray_origin = if cam.defocus_angle < 0.0 { cam.center } else { defocus_disk_sample(cam.clone()) };
-    let ray_time = random_double();
-    ray(ray_origin, pixel_sample - ray_origin, ray_time)
+    let pixel_sample = pixel_center + pixel_sample_square(cam);
+    let ray_origin = if cam.defocus_angle < 0.0 { cam.center } else { defocus_disk_sample(cam) };
+    let ray_time = cam.random_time();
+    let r = ray(ray_origin, pixel_sample - ray_origin, ray_time);
+    match cam.motion_at(ray_time) {
+        Some(pose) => pose.ray(&r),
+        None => r
+    }
 }
 
-pub fn render(cam: Arc<Camera>, world: Arc<HittableList>) {
+pub fn render(cam: Arc<Camera>, world: Arc<dyn Hit + Send + Sync>) {
+    let start_time = Instant::now();
+    let mut statistics = RenderStatistics::default();
+    let aovs: &[Aov] = if cam.aovs.is_empty() || cam.integrator.supports_aovs() { &cam.aovs } else {
+        println!("The integrator does not report AOVs, only the beauty image will be written.");
+        &[]
+    };
+    let mut result = film(cam.image_width, cam.image_height, cam.samples_per_pixel, aovs, cam.light_groups.len());
+    if let Some(image) = cam.integrator.render_image(&scene(&cam, &*world), cam.samples_per_pixel, &mut statistics) {
+        result.add_beauty(&image);
+        write_image(&cam, &result, &start_time, &statistics);
+        return;
+    }
     let mut order: std::vec::Vec<Position> = std::vec::Vec::with_capacity((cam.image_width * cam.image_height) as usize);
-    let result: std::vec::Vec<Color> = vec![black(); (cam.image_width * cam.image_height) as usize];
     for j in 0..cam.image_height {
         for i in 0..cam.image_width {
             order.push(position(i, j));
@@ -114,7 +158,8 @@ pub fn render(cam: Arc<Camera>, world: Arc<HittableList>) {
     let order = Arc::new(Mutex::new(order));
     let result = Arc::new(Mutex::new(result));
     let complete_num = Arc::new(Mutex::new(0));
-    let start_time = Arc::new(Instant::now());
+    let start_time = Arc::new(start_time);
+    let total_statistics = Arc::new(Mutex::new(RenderStatistics::default()));
 
     println!("Start rendering.");
     let mut thread_handler = std::vec::Vec::new();
@@ -145,47 +190,161 @@ pub fn render(cam: Arc<Camera>, world: Arc<HittableList>) {
     for _i in 0..THREADS_NUM {
         let world = world.clone();
         let samples_per_pixel = cam.samples_per_pixel;
-        let max_depth = cam.max_depth;
+        let total_statistics = total_statistics.clone();
         let order = order.clone();
         let result = result.clone();
         let image_width = cam.image_width;
         let cam = cam.clone();
         let complete_num = complete_num.clone();
         thread_handler.push(thread::spawn(move || {
+            let scene = scene(&cam, &*world);
+            let mut statistics = RenderStatistics::default();
+            let mut splats = splats(cam.image_width, cam.image_height);
+            let mut sample = sample_aovs(cam.light_groups.len());
+            let mut pixel_aovs = sample_aovs(cam.light_groups.len());
             loop {
                 let mut order = order.lock().expect("Error occurred when trying to lock.");
-                if order.is_empty() { return; }
+                if order.is_empty() { break; }
                 let i = order[order.len() - 1].i;
                 let j = order[order.len() - 1].j;
                 order.pop();
                 drop(order);
                 let mut pixel_color = black();
+                pixel_aovs.clear();
                 for _k in 0..samples_per_pixel {
-                    let r = get_ray(cam.clone(), i, j);
-                    pixel_color += ray_color(cam.clone(), &r, max_depth, world.clone());
+                    let r = get_ray(&cam, i, j);
+                    sample.clear();
+                    pixel_color += cam.integrator.li(&r, &scene, &mut statistics, &mut splats, &mut sample);
+                    pixel_aovs.accumulate(&sample);
                 }
                 let mut res = result.lock().expect("Error occurred when trying to lock.");
-                res[(j * image_width + i) as usize] = pixel_color;
+                res.add_pixel((j * image_width + i) as usize, pixel_color, &pixel_aovs, samples_per_pixel as u32);
                 drop(res);
                 let mut complete_num = complete_num.lock().expect("Error occurred when trying to lock");
                 *complete_num += 1;
                 io::stdout().flush().expect("IO message error!");
             }
+            statistics.collect_traversal();
+            total_statistics.lock().expect("Error occurred when trying to lock.").merge(&statistics);
+            result.lock().expect("Error occurred when trying to lock.").add_beauty(splats.pixels());
         }));
     }
     for i in thread_handler { i.join().expect("Error occurred when joining threads"); }
-    println!("\nOutputting images.");
-    let mut file = File::create("Image.ppm").unwrap();
-    file.write(format!("P3\n{} {}\n255\n", cam.image_width, cam.image_height).as_ref()).unwrap();
     let result = result.lock().expect("Error occurred when trying to lock.");
-    for i in 0..result.len() {
-        result[i].write(&mut file, SAMPLES_PER_PIXEL);
-    }
+    let statistics = total_statistics.lock().expect("Error occurred when trying to lock.");
+    write_image(&cam, &result, &start_time, &statistics);
+}
+
+fn write_image(cam: &Camera, image: &Film, start_time: &Instant, statistics: &RenderStatistics) {
+    println!("\nOutputting images.");
+    image.write("Image", &cam.light_groups);
     println!("Output finished.");
     println!("Total time spent: {}ms", start_time.elapsed().as_millis());
+    println!("Average path length: {:.2} segments", statistics.average_path_length());
+    if statistics.traversal.rays > 0 {
+        println!("Traversal: {:.1} nodes visited and {:.1} primitives tested per ray.",
+                 statistics.traversal.average_traversal_steps(), statistics.traversal.average_primitive_tests());
+    }
 }
 
 impl Camera {
+    pub fn set_background(&mut self, background: Arc<dyn Background + Sync + Send>) {
+        // Whether a background can be importance sampled does not change, probe it once.
+        self.background_sampled = background.sample_direction().is_some();
+        self.background = background;
+    }
+    /// Emitters to sample explicitly at every diffuse bounce, typically quads and spheres with a
+    /// DiffuseLight material. They must also be part of the world, and every emitting surface of
+    /// the world should be listed here for the weights to add up.
+    pub fn set_lights(&mut self, lights: Arc<dyn Hit + Send + Sync>) {
+        self.lights = Some(lights);
+    }
+    /// Rays are spread uniformly over [open, close], in the same time units as the scene's motion.
+    /// Moving spheres travel during [0, 1], keyframed motion over its keyframes' times.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        assert!(open <= close, "The shutter cannot close before it opens.");
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+    /// The algorithm estimating each pixel sample, a path tracer by default.
+    pub fn set_integrator(&mut self, integrator: Arc<dyn Integrator + Send + Sync>) {
+        self.integrator = integrator;
+    }
+    /// Extra channels to write next to Image.ppm, as Image.<name>.pfm. Only integrators that
+    /// report AOVs fill them in, the path tracer does.
+    pub fn set_aovs(&mut self, aovs: &[Aov]) {
+        self.aovs = aovs.to_vec();
+    }
+    /// Emitters with one of materials get their light written to Image.light.<name>.pfm when
+    /// the LightGroups AOV is on.
+    pub fn add_light_group(&mut self, name: &str, materials: std::vec::Vec<Arc<dyn Scatter + Sync + Send>>) {
+        self.light_groups.push(light_group(name, materials));
+    }
+    /// Animate the camera with keyframes given in camera space (x right, y up, looking down -z)
+    /// relative to the pose set by look_from and look_at, e.g. a translation along x pans sideways.
+    pub fn set_motion(&mut self, motion: AnimatedTransform) {
+        self.motion = Some(motion);
+    }
+    /// The camera's displacement at time, None for a static camera.
+    fn motion_at(&self, time: f64) -> Option<Transform> {
+        // Move the camera in its own frame: to camera space, apply the pose, back to world space.
+        self.motion.as_ref().map(|motion| self.camera_to_world * motion.at(time) * self.camera_to_world.inverse())
+    }
+    pub fn image_size(&self) -> (i32, i32) { (self.image_width, self.image_height) }
+    /// A time within the shutter interval, uniformly distributed.
+    pub fn random_time(&self) -> f64 { self.shutter_open + random_double() * (self.shutter_close - self.shutter_open) }
+    /// Width of a pixel seen from the camera, as a distance per unit of distance from it.
+    pub fn pixel_spread(&self) -> f64 { self.pixel_delta_u.length() / self.focus_dist }
+    /// Distance of p in front of the camera at time, measured along the viewing direction.
+    pub fn depth(&self, p: &Point, time: f64) -> f64 {
+        let local = match self.motion_at(time) {
+            Some(pose) => pose.inverse().point(p),
+            None => *p
+        };
+        dot(&(local - self.center), &-self.w)
+    }
+    /// Solid angle density with which get_ray picks the direction of r from its origin.
+    pub fn pdf_direction(&self, r: &Ray) -> f64 {
+        let direction = match self.motion_at(r.time()) {
+            Some(pose) => pose.inverse().vector(r.direction()),
+            None => *r.direction()
+        };
+        let cos_theta = dot(&direction.unit(), &-self.w);
+        if cos_theta <= 0.0 { 0.0 } else { 1.0 / (self.film_area * cos_theta.powi(3)) }
+    }
+    /// Pick a lens point for light leaving p at time and find the pixel it lands on, None if p
+    /// is behind the camera or outside the image.
+    pub fn connect(&self, p: &Point, time: f64) -> Option<LensConnection> {
+        let pose = self.motion_at(time);
+        let local = match &pose {
+            Some(pose) => pose.inverse().point(p),
+            None => *p
+        };
+        let offset = rand_in_unit_disk();
+        let lens = self.center + self.lens_radius * (offset.x() * self.u + offset.y() * self.v);
+        let d = local - lens;
+        let depth = dot(&d, &-self.w);
+        if depth <= 0.0 {
+            return None;
+        }
+        // Where the line through the lens point crosses the focus plane decides the pixel.
+        let on_focus_plane = lens + (self.focus_dist / depth) * d - self.pixel00_loc;
+        let x = dot(&on_focus_plane, &self.pixel_delta_u) / self.pixel_delta_u.length_squared();
+        let y = dot(&on_focus_plane, &self.pixel_delta_v) / self.pixel_delta_v.length_squared();
+        let pixel = ((x + 0.5).floor() as i32, (y + 0.5).floor() as i32);
+        if pixel.0 < 0 || pixel.0 >= self.image_width || pixel.1 < 0 || pixel.1 >= self.image_height {
+            return None;
+        }
+        let lens_area = if self.lens_radius > 0.0 { PI * self.lens_radius * self.lens_radius } else { 1.0 };
+        let cos_theta = depth / d.length();
+        Some(LensConnection {
+            pixel,
+            lens: match &pose { Some(pose) => pose.point(&lens), None => lens },
+            importance: 1.0 / (self.film_area * lens_area * cos_theta.powi(4)),
+            pdf: 1.0 / lens_area,
+            cos_theta
+        })
+    }
     fn initialize(&mut self) {
         self.image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
         if self.image_height < 1 { self.image_height = 1; }
@@ -204,7 +363,11 @@ impl Camera {
         let viewport_upper_left = self.center - self.focus_dist * self.w - viewport_u / 2.0 - viewport_v / 2.0;
         self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
         let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
+        self.lens_radius = if self.defocus_angle < 0.0 { 0.0 } else { defocus_radius };
+        self.film_area = viewport_width * viewport_height / (self.focus_dist * self.focus_dist);
         self.defocus_dist_u = defocus_radius * self.u;
         self.defocus_dist_v = defocus_radius * self.v;
+        self.camera_to_world = frame_transform(&self.u, &self.v, &self.w, &self.center)
+            .expect("Camera basis must not be degenerate.");
     }
 }
\ No newline at end of file
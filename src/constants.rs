@@ -1,26 +1,62 @@
+use std::any::Any;
+use std::cell::RefCell;
 use rand::{Rng, thread_rng};
 use rand::seq::SliceRandom;
 use crate::basic::{Point, point, vec, Vec};
 
 pub const INFINITY: f64 = f64::INFINITY;
 pub const PI: f64 = std::f64::consts::PI;
+pub const SURFACE_EPSILON: f64 = 0.001; // Offset of rays leaving a surface, against self-intersection
+pub const VOLUME_EPSILON: f64 = 1e-8; // Offset of rays leaving a scattering event inside a medium
 
 pub fn linear_to_gamma(val: f64) -> f64 { val.sqrt() }
 
+pub fn gamma_to_linear(val: f64) -> f64 { val * val }
+
+pub fn srgb_to_linear(val: f64) -> f64 {
+    if val <= 0.04045 { val / 12.92 } else { ((val + 0.055) / 1.055).powf(2.4) }
+}
+
+/// The value following name on the command line, given as "name value" or "name=value".
+pub fn command_line_option(name: &str) -> Option<String> {
+    let args: std::vec::Vec<String> = std::env::args().collect();
+    let prefix = format!("{}=", name);
+    args.iter().position(|arg| arg == name).map(|i| args.get(i + 1).cloned().unwrap_or_default())
+        .or_else(|| args.iter().find_map(|arg| arg.strip_prefix(&prefix).map(str::to_string)))
+}
+
 /*
     From below are random functions.
 */
 
+/// Numbers in [0, 1) that random_double hands out in place of the thread's generator, e.g. the
+/// primary sample vector of a Metropolis sampler.
+pub trait RandomSource: Any {
+    fn next(&mut self) -> f64;
+}
+
+thread_local! {
+    static RANDOM_SOURCE: RefCell<Option<Box<dyn RandomSource>>> = const { RefCell::new(None) };
+}
+
 pub fn random_double() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen::<f64>()
+    let replaced = RANDOM_SOURCE.with(|source| source.borrow_mut().as_mut().map(|source| source.next()));
+    replaced.unwrap_or_else(|| rand::thread_rng().gen::<f64>())
+}
+
+/// Run f with every random_double on this thread drawn from source, which is handed back after.
+pub fn with_random_source<S: RandomSource + 'static, R>(source: Box<S>, f: impl FnOnce() -> R) -> (R, Box<S>) {
+    let previous = RANDOM_SOURCE.with(|slot| slot.replace(Some(source)));
+    let ret = f();
+    let source: Box<dyn Any> = RANDOM_SOURCE.with(|slot| slot.replace(previous)).expect("Random source was removed");
+    (ret, source.downcast::<S>().expect("Random source was replaced"))
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
     min + random_double() * (max - min)
 }
 
-pub fn random_shuffle<T>(sequence: &mut std::vec::Vec<T>) { sequence.shuffle(&mut thread_rng()); }
+pub fn random_shuffle<T>(sequence: &mut [T]) { sequence.shuffle(&mut thread_rng()); }
 
 /*
     From below are camera parameters.
@@ -29,15 +65,37 @@ pub fn random_shuffle<T>(sequence: &mut std::vec::Vec<T>) { sequence.shuffle(&mu
 pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
 pub const IMAGE_WIDTH: i32 = 400;
 pub const SAMPLES_PER_PIXEL: i32 = 100;
-pub const MAX_DEPTH: i32 = 50;
+pub const MIN_DEPTH: i32 = 5; // Bounces before Russian roulette may end a path
+pub const MAX_DEPTH: Option<i32> = None; // Optional hard cap on bounces, which biases the image
+pub const MAX_SURVIVAL: f64 = 0.95; // Keeps paths with undiminished throughput, e.g. in glass, finite
+pub const BDPT_MAX_DEPTH: i32 = 10; // Bounces of the longest path a bidirectional tracer builds
+pub const SPPM_MAX_DEPTH: i32 = 10; // Bounces of camera and photon paths in photon mapping
+pub const SPPM_INITIAL_RADIUS: f64 = 4.0; // Gather radius of the first iteration, in pixel footprints
+pub const SPPM_ALPHA: f64 = 2.0 / 3.0; // Fraction of new photons kept as the gather radius shrinks
+pub const MLT_BOOTSTRAP_SAMPLES: usize = 100_000; // Paths estimating the image brightness for Metropolis
+pub const MLT_CHAINS: usize = 1000; // Independent Markov chains the mutations are spread over
+pub const MLT_SIGMA: f64 = 0.01; // Standard deviation of a small step in primary sample space
+pub const MLT_LARGE_STEP_PROBABILITY: f64 = 0.3;
+pub const AO_RADIUS: f64 = 1.0; // Reach of ambient occlusion rays when none is given
+pub const DEPTH_VIEW_FAR: f64 = 50.0; // Depth shown as white in the depth view when none is given
 pub const VFOV: f64 = 20.0;
 pub const LOOK_FROM: Point = point(13.0, 2.0, 3.0);
 pub const LOOK_AT: Point = point(0.0, 0.0, 0.0);
 pub const VUP: Vec = vec(0.0, 1.0, 0.0);
 pub const DEFOCUS_ANGLE: f64 = 0.6;
 pub const FOCUS_DIST: f64 = 10.0;
+pub const SHUTTER_OPEN: f64 = 0.0;
+pub const SHUTTER_CLOSE: f64 = 1.0;
 
 /*
     From below are multithreading parameters.
  */
-pub const THREADS_NUM: i32 = 7;
\ No newline at end of file
+pub const THREADS_NUM: i32 = 7;
+
+/*
+    From below are BVH parameters.
+ */
+pub const SAH_BUCKETS: usize = 12;
+pub const MAX_PRIMS_IN_LEAF: usize = 4;
+pub const BVH_MAX_DEPTH: usize = 64; // Deeper subtrees become leaves, bounds the traversal stack
+pub const SAH_TRAVERSAL_COST: f64 = 0.125; // Relative to the cost of one primitive test
@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use crate::basic::*;
+use crate::material::Scatter;
+
+/// Channels a render can write next to the beauty image, each to a file of its own.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Aov {
+    Albedo, // Of the first hit
+    Normal, // Shading normal of the first hit, components in [-1, 1]
+    Depth, // Distance of the first hit along the viewing direction, zero where rays miss
+    Direct, // Light reaching the camera after at most one bounce
+    Indirect, // Everything else, direct and indirect add up to the beauty image
+    LightGroups, // One image per light group of the camera
+    SampleCount
+}
+
+/// Emitters whose light is also written to an image of its own, identified by their materials.
+pub struct LightGroup {
+    pub name: String,
+    materials: std::vec::Vec<Arc<dyn Scatter + Sync + Send>>
+}
+
+pub fn light_group(name: &str, materials: std::vec::Vec<Arc<dyn Scatter + Sync + Send>>) -> LightGroup {
+    LightGroup { name: name.to_string(), materials }
+}
+
+impl LightGroup {
+    pub fn contains(&self, mat: &Arc<dyn Scatter + Sync + Send>) -> bool {
+        self.materials.iter().any(|m| std::ptr::addr_eq(Arc::as_ptr(m), Arc::as_ptr(mat)))
+    }
+}
+
+/// What an integrator reports about a camera sample besides its radiance. Integrators that do
+/// not support AOVs leave it untouched. Also used to sum the samples of a pixel.
+#[derive(Clone)]
+pub struct SampleAovs {
+    pub albedo: Color,
+    pub normal: Vec,
+    pub depth: f64,
+    pub direct: Color,
+    pub indirect: Color,
+    pub light_groups: std::vec::Vec<Color> // Indexed like the camera's light groups
+}
+
+pub fn sample_aovs(light_groups: usize) -> SampleAovs {
+    SampleAovs { albedo: black(), normal: empty_vec(), depth: 0.0, direct: black(), indirect: black(), light_groups: vec![black(); light_groups] }
+}
+
+impl SampleAovs {
+    pub fn clear(&mut self) {
+        let light_groups = std::mem::take(&mut self.light_groups);
+        *self = sample_aovs(light_groups.len());
+    }
+    /// Record light that reached the camera after the given number of bounces, from the light
+    /// group with index group if it belongs to one.
+    pub fn add_light(&mut self, light: Color, bounces: i32, group: Option<usize>) {
+        if bounces <= 1 {
+            self.direct += light;
+        } else {
+            self.indirect += light;
+        }
+        if let Some(sum) = group.and_then(|group| self.light_groups.get_mut(group)) {
+            *sum += light;
+        }
+    }
+    pub fn accumulate(&mut self, other: &SampleAovs) {
+        self.albedo += other.albedo;
+        self.normal += other.normal;
+        self.depth += other.depth;
+        self.direct += other.direct;
+        self.indirect += other.indirect;
+        for (sum, light) in self.light_groups.iter_mut().zip(&other.light_groups) {
+            *sum += *light;
+        }
+    }
+}
+
+/// Sums of the samples of every pixel: the beauty image and the AOVs asked for.
+pub struct Film {
+    width: i32,
+    height: i32,
+    samples_per_pixel: i32, // Camera samples the beauty image is the sum of
+    aovs: std::vec::Vec<Aov>,
+    beauty: std::vec::Vec<Color>,
+    sums: std::vec::Vec<SampleAovs>,
+    samples: std::vec::Vec<u32>
+}
+
+pub fn film(width: i32, height: i32, samples_per_pixel: i32, aovs: &[Aov], light_groups: usize) -> Film {
+    let count = (width * height) as usize;
+    Film {
+        width,
+        height,
+        samples_per_pixel,
+        aovs: aovs.to_vec(),
+        beauty: vec![black(); count],
+        sums: if aovs.is_empty() { std::vec::Vec::new() } else { vec![sample_aovs(light_groups); count] },
+        samples: vec![0; count]
+    }
+}
+
+/// Write a little endian portable float map, which stores rows from the bottom up.
+fn write_pfm(path: &str, width: i32, height: i32, pixel: impl Fn(usize) -> Color) {
+    let mut file = BufWriter::new(File::create(path).unwrap());
+    file.write_all(format!("PF\n{} {}\n-1.0\n", width, height).as_ref()).unwrap();
+    for j in (0..height).rev() {
+        for i in 0..width {
+            let c = pixel((j * width + i) as usize);
+            for value in [c.r(), c.g(), c.b()] {
+                file.write_all(&(value as f32).to_le_bytes()).unwrap();
+            }
+        }
+    }
+    file.flush().unwrap();
+}
+
+impl Film {
+    /// Add the sums of samples camera samples of the pixel at index.
+    pub fn add_pixel(&mut self, index: usize, beauty: Color, aovs: &SampleAovs, samples: u32) {
+        self.beauty[index] += beauty;
+        if let Some(sum) = self.sums.get_mut(index) {
+            sum.accumulate(aovs);
+        }
+        self.samples[index] += samples;
+    }
+    /// Add light to the beauty image alone, like splats or the result of a whole-image integrator.
+    pub fn add_beauty(&mut self, image: &[Color]) {
+        for (pixel, light) in self.beauty.iter_mut().zip(image) {
+            *pixel += *light;
+        }
+    }
+    /// Write the beauty image to stem.ppm and every AOV to stem.<name>.pfm, light groups to
+    /// stem.light.<group name>.pfm.
+    pub fn write(&self, stem: &str, light_groups: &[LightGroup]) {
+        let mut file = File::create(format!("{}.ppm", stem)).unwrap();
+        file.write_all(format!("P3\n{} {}\n255\n", self.width, self.height).as_ref()).unwrap();
+        for pixel in &self.beauty {
+            pixel.write(&mut file, self.samples_per_pixel);
+        }
+        if self.aovs.is_empty() {
+            return;
+        }
+        let average = |index: usize, value: Color| if self.samples[index] == 0 { black() } else { value / self.samples[index] as f64 };
+        let write = |name: &str, value: &dyn Fn(&SampleAovs) -> Color| {
+            write_pfm(&format!("{}.{}.pfm", stem, name), self.width, self.height, |index| average(index, value(&self.sums[index])));
+        };
+        for aov in &self.aovs {
+            match aov {
+                Aov::Albedo => write("albedo", &|sum| sum.albedo),
+                Aov::Normal => write("normal", &|sum| color(sum.normal.x(), sum.normal.y(), sum.normal.z())),
+                Aov::Depth => write("depth", &|sum| sum.depth * white()),
+                Aov::Direct => write("direct", &|sum| sum.direct),
+                Aov::Indirect => write("indirect", &|sum| sum.indirect),
+                Aov::LightGroups => {
+                    for (group, light_group) in light_groups.iter().enumerate() {
+                        write(&format!("light.{}", light_group.name), &|sum| sum.light_groups[group]);
+                    }
+                }
+                Aov::SampleCount => {
+                    write_pfm(&format!("{}.samples.pfm", stem), self.width, self.height, |index| self.samples[index] as f64 * white());
+                }
+            }
+        }
+    }
+}
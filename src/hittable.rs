@@ -1,9 +1,29 @@
 mod sphere;
 mod hittable_list;
+mod bvh;
+mod sah_bvh;
+mod triangle;
+mod mesh;
+mod bump;
+mod quad;
+mod instance;
+mod animated_instance;
+mod constant_medium;
+mod grid_medium;
 
 use std::sync::Arc;
 pub use sphere::*;
 pub use hittable_list::*;
+pub use bvh::*;
+pub use sah_bvh::*;
+pub use triangle::*;
+pub use mesh::*;
+pub use bump::*;
+pub use quad::*;
+pub use instance::*;
+pub use animated_instance::*;
+pub use constant_medium::*;
+pub use grid_medium::*;
 use crate::material::{lambertian, Scatter};
 
 use super::basic::*;
@@ -11,26 +31,70 @@ use super::basic::*;
 pub struct HitRecord {
     pub p: Point,
     pub t: f64,
-    pub normal: Vec,
+    pub normal: Vec, // Shading normal, on the side the ray came from
+    pub geometric_normal: Vec, // Normal of the actual surface, on the same side
     pub front_face: bool,
-    pub mat: Arc<dyn Scatter + Sync + Send>
+    pub volume: bool, // A scattering event inside a medium: normal and front_face carry no meaning
+    pub u: f64, // Surface coordinates of the hit point
+    pub v: f64,
+    pub mat: Arc<dyn Scatter + Sync + Send>,
+    pub emission: Color // Given off by a medium at a collision, on top of what mat emits
 }
 
 impl HitRecord {
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec) {
         self.front_face = dot(r.direction(), outward_normal) < 0.0;
         self.normal = if self.front_face { *outward_normal } else { -*outward_normal };
+        self.geometric_normal = self.normal;
+    }
+    /// Light leaving the hit point by emission.
+    pub fn emitted(&self) -> Color {
+        (*self.mat).emitted(self.u, self.v, &self.p) + self.emission
     }
 }
 
 fn empty_record() -> HitRecord {
-    HitRecord { p: empty_point(), t: 0.0, normal: empty_vec(), front_face: false, mat: Arc::new(lambertian::empty_lambertian()) }
-}
-
-fn hit_record(p: Point, t: f64, normal: Vec, front_face: bool, mat: Arc<dyn Scatter + Sync + Send>) -> HitRecord {
-    HitRecord { p, t, normal, front_face, mat }
+    HitRecord {
+        p: empty_point(), t: 0.0, normal: empty_vec(), geometric_normal: empty_vec(), front_face: false, volume: false, u: 0.0, v: 0.0,
+        mat: Arc::new(lambertian::empty_lambertian()), emission: black()
+    }
 }
 
 pub trait Hit {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord>;
+    /// A box enclosing the object at any time, so it holds whatever the shutter interval.
+    fn bounding_box(&self) -> Aabb;
+    /// Fraction of light travelling along r within ray_t that gets through, for shadow rays.
+    /// Surfaces are opaque, participating media override this with their own estimate.
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 {
+        if self.hit(r, ray_t).is_some() { 0.0 } else { 1.0 }
+    }
+    /// Call f with every material hits on the object can carry, in an order that depends only on
+    /// how the scene was built. Materials may come up more than once.
+    fn for_each_material(&self, _f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) {}
+    /// Whether random and pdf_value sample directions towards the object, so it can be light
+    /// sampled. Lists skip members that cannot.
+    fn samples_directions(&self) -> bool {
+        false
+    }
+    /// Solid angle density with which random(origin, time) picks direction, for objects used as lights.
+    fn pdf_value(&self, _origin: &Point, _direction: &Vec, _time: f64) -> f64 {
+        0.0
+    }
+    /// A direction from origin towards a random point of the object as it is at time. Only
+    /// meaningful if samples_directions.
+    fn random(&self, _origin: &Point, _time: f64) -> Vec {
+        vec(1.0, 0.0, 0.0)
+    }
+    /// A random point of the surface at time with its outward normal, surface coordinates and
+    /// material, and the area density of picking it. Spheres, quads, lists and instances of them
+    /// can start light paths, for anything else, triangles and media included, this is None.
+    fn sample_surface(&self, _time: f64) -> Option<(HitRecord, f64)> {
+        None
+    }
+    /// Area density with which sample_surface(time) picks p, whose surface normal is normal,
+    /// zero if p is not on the surface.
+    fn surface_pdf(&self, _p: &Point, _normal: &Vec, _time: f64) -> f64 {
+        0.0
+    }
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use crate::material::Scatter;
+use super::{Hit, HitRecord};
+use super::instance::{transform_pdf_value, transform_surface_pdf, transform_surface_sample};
+use super::super::basic::*;
+
+/// Like `Instance`, but the transform follows keyframes over time, blurring the object across
+/// the camera's shutter interval.
+pub struct AnimatedInstance {
+    object: Arc<dyn Hit + Send + Sync>,
+    animation: AnimatedTransform,
+    bbox: Aabb
+}
+
+pub fn animated_instance(object: Arc<dyn Hit + Send + Sync>, animation: AnimatedTransform) -> AnimatedInstance {
+    let bbox = animation.bounds(&object.bounding_box());
+    AnimatedInstance { object, animation, bbox }
+}
+
+impl Hit for AnimatedInstance {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let transform = self.animation.at(r.time());
+        let object_ray = transform.inverse().ray(r);
+        let mut rec = self.object.hit(&object_ray, ray_t)?;
+        rec.p = transform.point(&rec.p);
+        rec.normal = transform.normal(&rec.normal).unit();
+        rec.geometric_normal = transform.normal(&rec.geometric_normal).unit();
+        Some(rec)
+    }
+    fn bounding_box(&self) -> Aabb { self.bbox }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { self.object.for_each_material(f) }
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 {
+        self.object.transmittance(&self.animation.at(r.time()).inverse().ray(r), ray_t)
+    }
+    fn samples_directions(&self) -> bool { self.object.samples_directions() }
+    fn pdf_value(&self, origin: &Point, direction: &Vec, time: f64) -> f64 {
+        transform_pdf_value(&self.animation.at(time), &*self.object, origin, direction, time)
+    }
+    fn random(&self, origin: &Point, time: f64) -> Vec {
+        let transform = self.animation.at(time);
+        transform.vector(&self.object.random(&transform.inverse().point(origin), time))
+    }
+    fn sample_surface(&self, time: f64) -> Option<(HitRecord, f64)> {
+        Some(transform_surface_sample(&self.animation.at(time), self.object.sample_surface(time)?))
+    }
+    fn surface_pdf(&self, p: &Point, normal: &Vec, time: f64) -> f64 {
+        transform_surface_pdf(&self.animation.at(time), &*self.object, p, normal, time)
+    }
+}
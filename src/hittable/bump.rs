@@ -0,0 +1,50 @@
+use std::sync::Arc;
+use crate::material::Scatter;
+use crate::texture::perlin::Perlin;
+use super::{Hit, HitRecord};
+use super::super::basic::*;
+
+/// Wraps an object and perturbs its shading normals with the gradient of an fBm height field,
+/// giving the look of a rough surface without changing the geometry.
+pub struct Bump {
+    object: Arc<dyn Hit + Send + Sync>,
+    noise: Arc<Perlin>,
+    scale: f64,    // Spatial frequency of the height field
+    strength: f64, // Height of the bumps relative to their width
+    octaves: i32
+}
+
+pub fn bump(object: Arc<dyn Hit + Send + Sync>, noise: Arc<Perlin>, scale: f64, strength: f64, octaves: i32) -> Bump {
+    Bump { object, noise, scale, strength, octaves }
+}
+
+impl Bump {
+    fn height(&self, p: &Point) -> f64 {
+        self.noise.fbm(&(self.scale * *p), self.octaves, 0.5)
+    }
+}
+
+impl Hit for Bump {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut rec = self.object.hit(r, ray_t)?;
+        // Central differences of the height field, in world units.
+        let eps = 1e-3 / self.scale;
+        let p = rec.p;
+        let gradient = vec(
+            self.height(&(p + vec(eps, 0.0, 0.0))) - self.height(&(p - vec(eps, 0.0, 0.0))),
+            self.height(&(p + vec(0.0, eps, 0.0))) - self.height(&(p - vec(0.0, eps, 0.0))),
+            self.height(&(p + vec(0.0, 0.0, eps))) - self.height(&(p - vec(0.0, 0.0, eps)))
+        ) / (2.0 * eps * self.scale);
+        let n = rec.normal;
+        let tangent_gradient = gradient - dot(&gradient, &n) * n;
+        let perturbed = (n - self.strength * tangent_gradient).unit();
+        // Never tilt the normal past the surface, or the material would scatter into it.
+        if dot(&perturbed, &n) > 0.0 {
+            rec.normal = perturbed;
+        }
+        Some(rec)
+    }
+    fn bounding_box(&self) -> Aabb { self.object.bounding_box() }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { self.object.for_each_material(f) }
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 { self.object.transmittance(r, ray_t) }
+}
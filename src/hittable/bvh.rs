@@ -0,0 +1,91 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::basic::{Aabb, aabb_union, empty_aabb, Interval, interval, Ray};
+use crate::material::Scatter;
+use super::{Hit, HitRecord, HittableList};
+use super::sah_bvh::counted_traversal;
+
+/// A node of a bounding volume hierarchy. Leaves point directly at the primitives,
+/// a node with a single primitive stores it in both children.
+pub struct BvhNode {
+    left: Arc<dyn Hit + Send + Sync>,
+    right: Arc<dyn Hit + Send + Sync>,
+    bbox: Aabb,
+    leaf: bool // Children are primitives rather than nodes
+}
+
+/// Build a BVH over all objects of the list, splitting at the median of the longest axis.
+pub fn bvh_node(list: HittableList) -> BvhNode {
+    let mut objects = list.objects;
+    if objects.is_empty() {
+        let empty: Arc<dyn Hit + Send + Sync> = Arc::new(super::empty_hittable_list());
+        return BvhNode { left: empty.clone(), right: empty, bbox: empty_aabb(), leaf: true };
+    }
+    let len = objects.len();
+    bvh_node_range(&mut objects, 0, len)
+}
+
+fn box_compare(a: &Arc<dyn Hit + Send + Sync>, b: &Arc<dyn Hit + Send + Sync>, axis: i32) -> Ordering {
+    let a_axis = a.bounding_box().axis(axis);
+    let b_axis = b.bounding_box().axis(axis);
+    a_axis.min.partial_cmp(&b_axis.min).unwrap_or(Ordering::Equal)
+}
+
+fn bvh_node_range(objects: &mut [Arc<dyn Hit + Send + Sync>], start: usize, end: usize) -> BvhNode {
+    let mut bbox = empty_aabb();
+    for object in &objects[start..end] {
+        bbox = aabb_union(&bbox, &object.bounding_box());
+    }
+    let axis = bbox.longest_axis();
+
+    let span = end - start;
+    let (left, right): (Arc<dyn Hit + Send + Sync>, Arc<dyn Hit + Send + Sync>) = if span == 1 {
+        (objects[start].clone(), objects[start].clone())
+    } else if span == 2 {
+        (objects[start].clone(), objects[start + 1].clone())
+    } else {
+        objects[start..end].sort_by(|a, b| box_compare(a, b, axis));
+        let mid = start + span / 2;
+        (Arc::new(bvh_node_range(objects, start, mid)), Arc::new(bvh_node_range(objects, mid, end)))
+    };
+    BvhNode { left, right, bbox, leaf: span <= 2 }
+}
+
+impl Hit for BvhNode {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        counted_traversal(|counts| {
+            counts.node_visits += 1;
+            if !self.bbox.hit(r, ray_t) {
+                return None;
+            }
+            let single = Arc::ptr_eq(&self.left, &self.right);
+            if self.leaf {
+                counts.primitive_tests += if single { 1 } else { 2 };
+            }
+            let hit_left = self.left.hit(r, ray_t);
+            if single {
+                return hit_left;
+            }
+            let right_max = if let Some(rec) = &hit_left { rec.t } else { ray_t.max };
+            let hit_right = self.right.hit(r, interval(ray_t.min, right_max));
+            hit_right.or(hit_left)
+        })
+    }
+    fn bounding_box(&self) -> Aabb { self.bbox }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) {
+        self.left.for_each_material(f);
+        if !Arc::ptr_eq(&self.left, &self.right) {
+            self.right.for_each_material(f);
+        }
+    }
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 {
+        if !self.bbox.hit(r, ray_t) {
+            return 1.0;
+        }
+        let left = self.left.transmittance(r, ray_t);
+        if left == 0.0 || Arc::ptr_eq(&self.left, &self.right) {
+            return left;
+        }
+        left * self.right.transmittance(r, ray_t)
+    }
+}
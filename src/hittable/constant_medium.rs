@@ -0,0 +1,86 @@
+use std::sync::Arc;
+use crate::constants::{INFINITY, random_double};
+use crate::material::{isotropic, Scatter};
+use super::{empty_record, Hit, HitRecord};
+use super::super::basic::*;
+
+const MAX_CROSSINGS: usize = 64;
+
+/// A homogeneous participating medium such as smoke or fog filling a closed boundary. A ray
+/// travelling a distance d inside scatters with probability 1 - exp(-density * d).
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hit + Send + Sync>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Scatter + Sync + Send>
+}
+
+pub fn constant_medium(boundary: Arc<dyn Hit + Send + Sync>, density: f64, albedo: Color) -> ConstantMedium {
+    ConstantMedium { boundary, neg_inv_density: -1.0 / density, phase_function: Arc::new(isotropic::isotropic(albedo)) }
+}
+
+pub fn constant_medium_phase(boundary: Arc<dyn Hit + Send + Sync>, density: f64, phase_function: Arc<dyn Scatter + Sync + Send>) -> ConstantMedium {
+    ConstantMedium { boundary, neg_inv_density: -1.0 / density, phase_function }
+}
+
+/// The parts of the ray's line inside a closed boundary, as sorted (t_enter, t_exit) pairs. Every
+/// crossing is classified by the side it hits, so nested shells (a hollow boundary, or several
+/// boundaries in one list) and rays starting inside are handled alike.
+pub(super) fn inside_segments(boundary: &(dyn Hit + Send + Sync), r: &Ray) -> std::vec::Vec<(f64, f64)> {
+    let mut segments = std::vec::Vec::new();
+    let mut depth = 0;
+    let mut enter = -INFINITY;
+    let mut t = -INFINITY;
+    for _ in 0..MAX_CROSSINGS {
+        let Some(rec) = boundary.hit(r, interval(t, INFINITY)) else { break; };
+        if rec.front_face {
+            if depth == 0 { enter = rec.t; }
+            depth += 1;
+        } else if depth > 0 {
+            depth -= 1;
+            if depth == 0 { segments.push((enter, rec.t)); }
+        } else {
+            // Leaving without having entered: the line started inside.
+            segments.push((-INFINITY, rec.t));
+        }
+        t = rec.t + 1e-4;
+    }
+    segments
+}
+
+impl Hit for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let ray_length = r.direction().length();
+        let mut hit_distance = self.neg_inv_density * random_double().ln();
+        for (enter, exit) in inside_segments(&*self.boundary, r) {
+            let t0 = enter.max(ray_t.min);
+            let t1 = exit.min(ray_t.max);
+            if t0 >= t1 {
+                continue;
+            }
+            let distance_inside = (t1 - t0) * ray_length;
+            if hit_distance <= distance_inside {
+                let mut rec = empty_record();
+                rec.t = t0 + hit_distance / ray_length;
+                rec.p = r.at(rec.t);
+                rec.normal = vec(1.0, 0.0, 0.0); // Arbitrary
+                rec.geometric_normal = rec.normal;
+                rec.front_face = true;
+                rec.volume = true;
+                rec.mat = self.phase_function.clone();
+                return Some(rec);
+            }
+            hit_distance -= distance_inside;
+        }
+        None
+    }
+    fn bounding_box(&self) -> Aabb { self.boundary.bounding_box() }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { f(&self.phase_function) }
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 {
+        let ray_length = r.direction().length();
+        let mut distance_inside = 0.0;
+        for (enter, exit) in inside_segments(&*self.boundary, r) {
+            distance_inside += (exit.min(ray_t.max) - enter.max(ray_t.min)).max(0.0) * ray_length;
+        }
+        (distance_inside / self.neg_inv_density).exp()
+    }
+}
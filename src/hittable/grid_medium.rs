@@ -0,0 +1,188 @@
+use std::sync::Arc;
+use crate::constants::random_double;
+use crate::material::henyey_greenstein::henyey_greenstein;
+use crate::material::Scatter;
+use super::{empty_record, Hit, HitRecord};
+use super::super::basic::*;
+
+/// A dense nx * ny * nz grid of samples stored with x varying fastest, then y, then z. Values sit
+/// at voxel centers and are interpolated trilinearly in between.
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    values: std::vec::Vec<f64>
+}
+
+pub fn voxel_grid(nx: usize, ny: usize, nz: usize, values: std::vec::Vec<f64>) -> VoxelGrid {
+    assert!(nx > 0 && ny > 0 && nz > 0, "voxel grid must not be empty");
+    assert_eq!(values.len(), nx * ny * nz, "voxel grid expects nx * ny * nz values");
+    VoxelGrid { nx, ny, nz, values }
+}
+
+impl VoxelGrid {
+    pub fn dimensions(&self) -> (usize, usize, usize) { (self.nx, self.ny, self.nz) }
+    pub fn max_value(&self) -> f64 { self.values.iter().cloned().fold(0.0, f64::max) }
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 { self.values[(z * self.ny + y) * self.nx + x] }
+    /// Interpolated value at local coordinates in [0, 1]^3, clamped to the outermost voxel centers.
+    pub fn sample(&self, local: &Point) -> f64 {
+        let coord = |u: f64, n: usize| -> (usize, usize, f64) {
+            let x = (u * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i = (x as usize).min(n - 1);
+            (i, (i + 1).min(n - 1), x - i as f64)
+        };
+        let (x0, x1, fx) = coord(local.x(), self.nx);
+        let (y0, y1, fy) = coord(local.y(), self.ny);
+        let (z0, z1, fz) = coord(local.z(), self.nz);
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let plane = |z: usize| lerp(
+            lerp(self.voxel(x0, y0, z), self.voxel(x1, y0, z), fx),
+            lerp(self.voxel(x0, y1, z), self.voxel(x1, y1, z), fx),
+            fy
+        );
+        lerp(plane(z0), plane(z1), fz)
+    }
+}
+
+/// Light given off by a grid medium. `Grid` scales a color by a second grid, `Temperature` reads
+/// a grid of temperatures in Kelvin and emits blackbody radiation scaled by the factor.
+pub enum VolumeEmission {
+    None,
+    Grid(Arc<VoxelGrid>, Color),
+    Temperature(Arc<VoxelGrid>, f64)
+}
+
+/// Blackbody radiance at one representative wavelength per channel, relative to a 6500 K body at
+/// 555 nm so that temperatures around daylight come out near 1.
+pub fn blackbody(temperature: f64) -> Color {
+    const HC_OVER_K: f64 = 1.4387769e-2; // Second radiation constant, in m K
+    let planck = |lambda: f64, t: f64| -> f64 {
+        if t <= 0.0 { 0.0 } else { 1.0 / (lambda.powi(5) * ((HC_OVER_K / (lambda * t)).exp() - 1.0)) }
+    };
+    let reference = planck(555e-9, 6500.0);
+    color(planck(700e-9, temperature), planck(546.1e-9, temperature), planck(435.8e-9, temperature)) / reference
+}
+
+/// A heterogeneous medium whose density comes from a voxel grid stretched over `bounds`.
+/// Collisions are found with delta tracking against the largest density in the grid, shadow
+/// rays estimate transmittance with ratio tracking.
+pub struct GridMedium {
+    density: Arc<VoxelGrid>,
+    bounds: Aabb,
+    density_scale: f64,
+    majorant: f64,
+    albedo: Color,
+    phase_function: Arc<dyn Scatter + Sync + Send>,
+    emission: VolumeEmission
+}
+
+/// `albedo` is the single scattering albedo and `g` the Henyey-Greenstein anisotropy. The bounds
+/// must have a positive, finite size along every axis.
+pub fn grid_medium(density: Arc<VoxelGrid>, bounds: Aabb, density_scale: f64, albedo: Color, g: f64) -> GridMedium {
+    assert!([bounds.x, bounds.y, bounds.z].iter().all(|i| i.size() > 0.0 && i.size().is_finite()),
+            "grid medium bounds must not be flat or unbounded");
+    let majorant = density.max_value() * density_scale;
+    GridMedium {
+        density,
+        bounds,
+        density_scale,
+        majorant,
+        albedo,
+        phase_function: Arc::new(henyey_greenstein(albedo, g)),
+        emission: VolumeEmission::None
+    }
+}
+
+impl GridMedium {
+    pub fn set_emission(&mut self, emission: VolumeEmission) { self.emission = emission; }
+    fn local(&self, p: &Point) -> Point {
+        let b = &self.bounds;
+        point((p.x() - b.x.min) / b.x.size(), (p.y() - b.y.min) / b.y.size(), (p.z() - b.z.min) / b.z.size())
+    }
+    fn density_at(&self, p: &Point) -> f64 { self.density_scale * self.density.sample(&self.local(p)) }
+    /// Emission at a collision. Only the absorbed fraction of collisions emits, the rest scatter.
+    fn emission_at(&self, p: &Point) -> Color {
+        let emitted = match &self.emission {
+            VolumeEmission::None => black(),
+            VolumeEmission::Grid(grid, tint) => grid.sample(&self.local(p)) * *tint,
+            VolumeEmission::Temperature(grid, scale) => *scale * blackbody(grid.sample(&self.local(p)))
+        };
+        (white() - self.albedo) * emitted
+    }
+}
+
+impl Hit for GridMedium {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let span = self.bounds.clip(r, ray_t)?;
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let step = 1.0 / (self.majorant * r.direction().length());
+        let mut t = span.min;
+        loop {
+            // Tentative collisions with the majorant, accepted with probability density / majorant.
+            t -= (1.0 - random_double()).ln() * step;
+            if t >= span.max {
+                return None;
+            }
+            let p = r.at(t);
+            if random_double() * self.majorant < self.density_at(&p) {
+                let mut rec = empty_record();
+                rec.t = t;
+                rec.p = p;
+                rec.normal = vec(1.0, 0.0, 0.0); // Arbitrary
+                rec.geometric_normal = rec.normal;
+                rec.front_face = true;
+                rec.volume = true;
+                rec.mat = self.phase_function.clone();
+                rec.emission = self.emission_at(&p);
+                return Some(rec);
+            }
+        }
+    }
+    fn bounding_box(&self) -> Aabb { self.bounds }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { f(&self.phase_function) }
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 {
+        let Some(span) = self.bounds.clip(r, ray_t) else { return 1.0; };
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+        let step = 1.0 / (self.majorant * r.direction().length());
+        let mut ret = 1.0;
+        let mut t = span.min;
+        loop {
+            t -= (1.0 - random_double()).ln() * step;
+            if t >= span.max {
+                return ret;
+            }
+            ret *= 1.0 - self.density_at(&r.at(t)) / self.majorant;
+            // Russian roulette keeps long paths through thick clouds cheap without bias.
+            if ret < 0.1 {
+                if random_double() < 0.5 {
+                    return 0.0;
+                }
+                ret *= 2.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::INFINITY;
+
+    #[test]
+    fn emitting_collisions_share_the_phase_function() {
+        let density = Arc::new(voxel_grid(1, 1, 1, std::vec::Vec::from([50.0])));
+        let bounds = aabb_points(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0));
+        let mut medium = grid_medium(density.clone(), bounds, 1.0, color(0.5, 0.5, 0.5), 0.0);
+        medium.set_emission(VolumeEmission::Grid(density, color(0.1, 0.2, 0.3)));
+        let r = ray(point(-5.0, 0.0, 0.0), vec(1.0, 0.0, 0.0), 0.0);
+        let first = medium.hit(&r, interval(0.001, INFINITY)).expect("The medium is dense enough to stop the ray");
+        let second = medium.hit(&r, interval(0.001, INFINITY)).expect("The medium is dense enough to stop the ray");
+        assert!(Arc::ptr_eq(&first.mat, &second.mat));
+        let emitted = first.emitted();
+        assert!((emitted.g() - 0.5 * 50.0 * 0.2).abs() < 1e-9);
+    }
+}
@@ -1,36 +1,132 @@
 use std::sync::Arc;
-use crate::basic::{Interval, interval, Ray};
+use crate::constants::random_double;
+use crate::material::Scatter;
 use super::{Hit, HitRecord};
+use super::sah_bvh::counted_traversal;
+use super::super::basic::*;
 
 #[derive(Clone)]
 pub struct HittableList {
-    objects: Vec<Arc<dyn Hit + Send + Sync>>
+    pub(super) objects: std::vec::Vec<Arc<dyn Hit + Send + Sync>>,
+    samplable: std::vec::Vec<usize>, // Objects that sample directions towards themselves
+    bbox: Aabb
 }
 
 pub fn empty_hittable_list() -> HittableList {
-    HittableList { objects: Vec::new() }
+    HittableList { objects: std::vec::Vec::new(), samplable: std::vec::Vec::new(), bbox: empty_aabb() }
+}
+
+pub fn hittable_list(object: Arc<dyn Hit + Send + Sync>) -> HittableList {
+    let mut ret = empty_hittable_list();
+    ret.add(object);
+    ret
 }
 
 impl HittableList {
     pub fn clear(&mut self) {
-        self.objects.clear()
+        self.objects.clear();
+        self.samplable.clear();
+        self.bbox = empty_aabb();
     }
-    pub fn add<T: Hit + 'static + Send + Sync>(&mut self, object: Arc<T>) {
+    pub fn add(&mut self, object: Arc<dyn Hit + Send + Sync>) {
+        self.bbox = aabb_union(&self.bbox, &object.bounding_box());
+        if object.samples_directions() {
+            self.samplable.push(self.objects.len());
+        }
         self.objects.push(object);
     }
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
 }
 
 impl Hit for HittableList {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
-        let mut hit_rec = None;
-        let mut closest_so_far = ray_t.max;
+        counted_traversal(|counts| {
+            let mut hit_rec = None;
+            let mut closest_so_far = ray_t.max;
 
+            for object in &self.objects {
+                counts.primitive_tests += 1;
+                if let Some(tmp_rec) = object.hit(r, interval(ray_t.min, closest_so_far)) {
+                    closest_so_far = tmp_rec.t;
+                    hit_rec = Some(tmp_rec);
+                }
+            }
+            hit_rec
+        })
+    }
+    fn bounding_box(&self) -> Aabb { self.bbox }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) {
+        for object in &self.objects {
+            object.for_each_material(f);
+        }
+    }
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 {
+        let mut ret = 1.0;
         for object in &self.objects {
-            if let Some(tmp_rec) = object.hit(r, interval(ray_t.min, closest_so_far)) {
-                closest_so_far = tmp_rec.t;
-                hit_rec = Some(tmp_rec);
+            ret *= object.transmittance(r, ray_t);
+            if ret == 0.0 {
+                break;
             }
         }
-        hit_rec
+        ret
+    }
+    fn samples_directions(&self) -> bool { !self.samplable.is_empty() }
+    /// Each object that samples directions is picked with equal probability, the others are
+    /// never sampled and add nothing.
+    fn pdf_value(&self, origin: &Point, direction: &Vec, time: f64) -> f64 {
+        if self.samplable.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.samplable.iter().map(|&i| self.objects[i].pdf_value(origin, direction, time)).sum();
+        sum / self.samplable.len() as f64
     }
-}
\ No newline at end of file
+    fn random(&self, origin: &Point, time: f64) -> Vec {
+        if self.samplable.is_empty() {
+            return vec(1.0, 0.0, 0.0);
+        }
+        let index = ((random_double() * self.samplable.len() as f64) as usize).min(self.samplable.len() - 1);
+        self.objects[self.samplable[index]].random(origin, time)
+    }
+    fn sample_surface(&self, time: f64) -> Option<(HitRecord, f64)> {
+        if self.objects.is_empty() {
+            return None;
+        }
+        let index = ((random_double() * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+        let (rec, pdf) = self.objects[index].sample_surface(time)?;
+        Some((rec, pdf / self.objects.len() as f64))
+    }
+    fn surface_pdf(&self, p: &Point, normal: &Vec, time: f64) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.objects.iter().map(|object| object.surface_pdf(p, normal, time)).sum();
+        sum / self.objects.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::INFINITY;
+    use crate::hittable::{quad, triangle};
+    use crate::material::diffuse_light::diffuse_light;
+
+    #[test]
+    fn light_samples_skip_objects_that_cannot_be_sampled() {
+        let mat = Arc::new(diffuse_light(color(1.0, 1.0, 1.0)));
+        let light = Arc::new(quad(point(-1.0, 2.0, -1.0), vec(2.0, 0.0, 0.0), vec(0.0, 0.0, 2.0), mat.clone()));
+        let mut lights = hittable_list(Arc::new(triangle(point(5.0, 0.0, 0.0), point(6.0, 0.0, 0.0), point(5.0, 1.0, 0.0), mat)));
+        lights.add(light.clone());
+        let origin = point(0.0, 0.0, 0.0);
+        for _ in 0..100 {
+            let direction = lights.random(&origin, 0.0);
+            assert!(light.hit(&ray(origin, direction, 0.0), interval(0.001, INFINITY)).is_some());
+            assert_eq!(lights.pdf_value(&origin, &direction, 0.0), light.pdf_value(&origin, &direction, 0.0));
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+use crate::material::Scatter;
+use super::{Hit, HitRecord};
+use super::super::basic::*;
+
+/// Places a shared object in the world with an object-to-world transform, so that one mesh can
+/// appear many times without copying its geometry.
+pub struct Instance {
+    object: Arc<dyn Hit + Send + Sync>,
+    transform: Transform,
+    bbox: Aabb
+}
+
+pub fn instance(object: Arc<dyn Hit + Send + Sync>, transform: Transform) -> Instance {
+    let bbox = transform.aabb(&object.bounding_box());
+    Instance { object, transform, bbox }
+}
+
+/// Bring a surface sample from object space to world space, converting its area density.
+pub(super) fn transform_surface_sample(transform: &Transform, sample: (HitRecord, f64)) -> (HitRecord, f64) {
+    let (mut rec, pdf) = sample;
+    let scale = transform.area_scale(&rec.geometric_normal);
+    rec.p = transform.point(&rec.p);
+    rec.normal = transform.normal(&rec.normal).unit();
+    rec.geometric_normal = transform.normal(&rec.geometric_normal).unit();
+    (rec, pdf / scale)
+}
+
+/// Area density of a world space point p with normal under transform, given the object's own density.
+pub(super) fn transform_surface_pdf(transform: &Transform, object: &dyn Hit, p: &Point, normal: &Vec, time: f64) -> f64 {
+    let inverse = transform.inverse();
+    // The inverse of the inverse transpose is the transpose, which takes normals back.
+    let object_normal = inverse.normal(normal);
+    let scale = transform.area_scale(&object_normal);
+    if scale <= 0.0 { 0.0 } else { object.surface_pdf(&inverse.point(p), &object_normal.unit(), time) / scale }
+}
+
+/// Solid angle density of a world space direction from origin towards an object sampled in its
+/// own space under transform. Directions bunch up or spread out wherever the transform is not rigid.
+pub(super) fn transform_pdf_value(transform: &Transform, object: &dyn Hit, origin: &Point, direction: &Vec, time: f64) -> f64 {
+    let inverse = transform.inverse();
+    let object_direction = inverse.vector(&direction.unit());
+    let pdf = object.pdf_value(&inverse.point(origin), &object_direction, time);
+    pdf * inverse.determinant().abs() / object_direction.length().powi(3)
+}
+
+impl Hit for Instance {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        // Intersect in object space, then bring the hit back to world space.
+        let object_ray = self.transform.inverse().ray(r);
+        let mut rec = self.object.hit(&object_ray, ray_t)?;
+        rec.p = self.transform.point(&rec.p);
+        rec.normal = self.transform.normal(&rec.normal).unit();
+        rec.geometric_normal = self.transform.normal(&rec.geometric_normal).unit();
+        Some(rec)
+    }
+    fn bounding_box(&self) -> Aabb { self.bbox }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { self.object.for_each_material(f) }
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 {
+        self.object.transmittance(&self.transform.inverse().ray(r), ray_t)
+    }
+    fn samples_directions(&self) -> bool { self.object.samples_directions() }
+    fn pdf_value(&self, origin: &Point, direction: &Vec, time: f64) -> f64 {
+        transform_pdf_value(&self.transform, &*self.object, origin, direction, time)
+    }
+    fn random(&self, origin: &Point, time: f64) -> Vec {
+        self.transform.vector(&self.object.random(&self.transform.inverse().point(origin), time))
+    }
+    fn sample_surface(&self, time: f64) -> Option<(HitRecord, f64)> {
+        Some(transform_surface_sample(&self.transform, self.object.sample_surface(time)?))
+    }
+    fn surface_pdf(&self, p: &Point, normal: &Vec, time: f64) -> f64 {
+        transform_surface_pdf(&self.transform, &*self.object, p, normal, time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{INFINITY, PI};
+    use crate::hittable::{quad, sphere};
+    use crate::material::diffuse_light::diffuse_light;
+
+    #[test]
+    fn light_samples_follow_the_transform() {
+        let mat = Arc::new(diffuse_light(color(1.0, 1.0, 1.0)));
+        let transform = translation(vec(0.0, 4.0, 1.0)) * rotation_z(35.0) * scaling(2.0, 0.5, 1.0);
+        let objects: [Arc<dyn Hit + Send + Sync>; 2] = [
+            Arc::new(sphere(point(0.0, 0.0, 0.0), 1.0, mat.clone())),
+            Arc::new(quad(point(-1.0, 0.0, -1.0), vec(2.0, 0.0, 0.0), vec(0.0, 0.5, 2.0), mat))
+        ];
+        let origin = point(0.5, -1.0, 0.0);
+        for object in objects {
+            let light = instance(object, transform);
+            assert!(light.samples_directions());
+            for _ in 0..100 {
+                let direction = light.random(&origin, 0.0);
+                assert!(light.hit(&ray(origin, direction, 0.0), interval(0.001, INFINITY)).is_some());
+                assert!(light.pdf_value(&origin, &direction, 0.0) > 0.0);
+            }
+            // The density over all directions integrates to one.
+            let samples = 200000;
+            let integral: f64 = (0..samples).map(|_| light.pdf_value(&origin, &rand_unit_vec(), 0.0)).sum::<f64>() * 4.0 * PI / samples as f64;
+            assert!((integral - 1.0).abs() < 0.05, "density integrates to {}", integral);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use crate::material::Scatter;
+use super::{Hit, HitRecord, HittableList, empty_hittable_list};
+use super::triangle::{moller_trumbore, triangle_bbox, triangle_record};
+use super::super::basic::*;
+
+/// Indices of one triangle into the attribute arrays of its mesh.
+#[derive(Copy, Clone, Debug)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub material: usize
+}
+
+/// An indexed triangle mesh whose attribute arrays are shared by all its triangles.
+pub struct Mesh {
+    positions: std::vec::Vec<Point>,
+    normals: std::vec::Vec<Vec>,
+    uvs: std::vec::Vec<(f64, f64)>,
+    faces: std::vec::Vec<MeshFace>,
+    materials: std::vec::Vec<Arc<dyn Scatter + Sync + Send>>,
+    two_sided: bool
+}
+
+pub fn mesh(positions: std::vec::Vec<Point>, normals: std::vec::Vec<Vec>, uvs: std::vec::Vec<(f64, f64)>, faces: std::vec::Vec<MeshFace>,
+            materials: std::vec::Vec<Arc<dyn Scatter + Sync + Send>>, two_sided: bool) -> Mesh {
+    Mesh { positions, normals, uvs, faces, materials, two_sided }
+}
+
+impl Mesh {
+    pub fn vertex_count(&self) -> usize { self.positions.len() }
+    pub fn face_count(&self) -> usize { self.faces.len() }
+}
+
+/// One face of a shared mesh.
+pub struct MeshTriangle {
+    mesh: Arc<Mesh>,
+    face: usize,
+    bbox: Aabb
+}
+
+/// All faces of the mesh as individual hittables, ready to be put into a BVH.
+pub fn mesh_triangles(mesh: &Arc<Mesh>) -> HittableList {
+    let mut ret = empty_hittable_list();
+    for (i, face) in mesh.faces.iter().enumerate() {
+        let [a, b, c] = face.vertices;
+        let bbox = triangle_bbox(&mesh.positions[a], &mesh.positions[b], &mesh.positions[c]);
+        ret.add(Arc::new(MeshTriangle { mesh: mesh.clone(), face: i, bbox }));
+    }
+    ret
+}
+
+impl Hit for MeshTriangle {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mesh = &*self.mesh;
+        let face = &mesh.faces[self.face];
+        let [a, b, c] = face.vertices;
+        let vertices = [mesh.positions[a], mesh.positions[b], mesh.positions[c]];
+        let (t, b1, b2) = moller_trumbore(r, ray_t, &vertices[0], &vertices[1], &vertices[2], mesh.two_sided)?;
+        let normals = face.normals.map(|[a, b, c]| [mesh.normals[a], mesh.normals[b], mesh.normals[c]]);
+        let uvs = match face.uvs {
+            Some([a, b, c]) => [mesh.uvs[a], mesh.uvs[b], mesh.uvs[c]],
+            None => [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]
+        };
+        Some(triangle_record(r, t, b1, b2, &vertices, normals.as_ref(), &uvs, &mesh.materials[face.material]))
+    }
+    fn bounding_box(&self) -> Aabb { self.bbox }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { f(&self.mesh.materials[self.mesh.faces[self.face].material]) }
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use crate::constants::{INFINITY, random_double};
+use crate::material::Scatter;
+use super::{Hit, empty_record, HitRecord, HittableList, empty_hittable_list};
+use super::super::basic::*;
+
+/// A parallelogram with corner q and edges u and v. The surface coordinates run from (0, 0) at q
+/// to (1, 1) at q + u + v, and the front face is the side u x v points to.
+pub struct Quad {
+    q: Point,
+    u: Vec,
+    v: Vec,
+    w: Vec, // n / (n . n), maps a planar offset to its (u, v) coordinates
+    normal: Vec,
+    d: f64, // Plane equation: normal . p = d
+    area: f64,
+    mat: Arc<dyn Scatter + Sync + Send>,
+    bbox: Aabb
+}
+
+pub fn quad(q: Point, u: Vec, v: Vec, mat: Arc<dyn Scatter + Sync + Send>) -> Quad {
+    let n = cross(&u, &v);
+    let normal = n.unit();
+    let bbox = aabb_union(&aabb_points(q, q + u + v), &aabb_points(q + u, q + v)).padded(1e-4);
+    Quad { q, u, v, w: n / dot(&n, &n), normal, d: dot(&normal, &q), area: n.length(), mat, bbox }
+}
+
+/// The six faces of the axis-aligned box with opposite corners a and b, facing outwards.
+pub fn make_box(a: Point, b: Point, mat: Arc<dyn Scatter + Sync + Send>) -> HittableList {
+    let mut sides = empty_hittable_list();
+    let min = point(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+    let max = point(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+    let dx = vec(max.x() - min.x(), 0.0, 0.0);
+    let dy = vec(0.0, max.y() - min.y(), 0.0);
+    let dz = vec(0.0, 0.0, max.z() - min.z());
+
+    sides.add(Arc::new(quad(point(min.x(), min.y(), max.z()), dx, dy, mat.clone()))); // Front
+    sides.add(Arc::new(quad(point(max.x(), min.y(), max.z()), -dz, dy, mat.clone()))); // Right
+    sides.add(Arc::new(quad(point(max.x(), min.y(), min.z()), -dx, dy, mat.clone()))); // Back
+    sides.add(Arc::new(quad(point(min.x(), min.y(), min.z()), dz, dy, mat.clone()))); // Left
+    sides.add(Arc::new(quad(point(min.x(), max.y(), max.z()), dx, -dz, mat.clone()))); // Top
+    sides.add(Arc::new(quad(point(min.x(), min.y(), min.z()), dx, dz, mat))); // Bottom
+    sides
+}
+
+impl Hit for Quad {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let denom = dot(&self.normal, r.direction());
+        // Parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - dot(&self.normal, r.origin())) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        let intersection = r.at(t);
+        let planar_hit = intersection - self.q;
+        let alpha = dot(&self.w, &cross(&planar_hit, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &planar_hit));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let mut rec = empty_record();
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(r, &self.normal);
+        rec.mat = self.mat.clone();
+        Some(rec)
+    }
+    fn bounding_box(&self) -> Aabb { self.bbox }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { f(&self.mat) }
+    fn samples_directions(&self) -> bool { true }
+    fn pdf_value(&self, origin: &Point, direction: &Vec, time: f64) -> f64 {
+        let Some(rec) = self.hit(&ray(*origin, *direction, time), interval(0.001, INFINITY)) else { return 0.0; };
+        // Convert the uniform density over the area into one over solid angle.
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (dot(direction, &rec.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+    fn random(&self, origin: &Point, _time: f64) -> Vec {
+        self.q + random_double() * self.u + random_double() * self.v - *origin
+    }
+    fn sample_surface(&self, _time: f64) -> Option<(HitRecord, f64)> {
+        let mut rec = empty_record();
+        rec.u = random_double();
+        rec.v = random_double();
+        rec.p = self.q + rec.u * self.u + rec.v * self.v;
+        rec.normal = self.normal;
+        rec.geometric_normal = self.normal;
+        rec.front_face = true;
+        rec.mat = self.mat.clone();
+        Some((rec, 1.0 / self.area))
+    }
+    fn surface_pdf(&self, p: &Point, _normal: &Vec, _time: f64) -> f64 {
+        let planar = *p - self.q;
+        let on_plane = (dot(&self.normal, p) - self.d).abs() < 1e-6 * (1.0 + self.d.abs());
+        let alpha = dot(&self.w, &cross(&planar, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &planar));
+        let tolerance = -1e-6..=1.0 + 1e-6;
+        if on_plane && tolerance.contains(&alpha) && tolerance.contains(&beta) { 1.0 / self.area } else { 0.0 }
+    }
+}
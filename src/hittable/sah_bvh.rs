@@ -0,0 +1,371 @@
+use std::cell::Cell;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::basic::{Aabb, aabb_points, aabb_union, empty_aabb, Interval, interval, Point, Ray};
+use crate::constants::{BVH_MAX_DEPTH, MAX_PRIMS_IN_LEAF, SAH_BUCKETS, SAH_TRAVERSAL_COST};
+use crate::material::Scatter;
+use super::{Hit, HitRecord, HittableList};
+
+/// One node of the flattened tree. For a leaf `count` primitives start at `offset`,
+/// for an interior node (`count == 0`) the first child follows immediately and the
+/// second child is at `offset`.
+#[derive(Copy, Clone)]
+struct LinearNode {
+    bbox: Aabb,
+    offset: usize,
+    count: usize,
+    axis: i32
+}
+
+pub struct BvhStatistics {
+    pub build_time: Duration,
+    pub primitive_count: usize,
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub max_depth: usize
+}
+
+/// Work done finding closest hits in SAH BVHs, BVH nodes and lists, summed per thread. A ray is
+/// counted once by the outermost structure it enters, nested ones add their work to it.
+#[derive(Copy, Clone, Default)]
+pub struct TraversalCounts {
+    pub rays: u64,
+    pub node_visits: u64,
+    pub primitive_tests: u64
+}
+
+impl TraversalCounts {
+    pub fn merge(&mut self, other: &TraversalCounts) {
+        self.rays += other.rays;
+        self.node_visits += other.node_visits;
+        self.primitive_tests += other.primitive_tests;
+    }
+    /// Average number of nodes visited per ray.
+    pub fn average_traversal_steps(&self) -> f64 { self.node_visits as f64 / self.rays.max(1) as f64 }
+    /// Average number of primitive intersection tests per ray.
+    pub fn average_primitive_tests(&self) -> f64 { self.primitive_tests as f64 / self.rays.max(1) as f64 }
+}
+
+thread_local! {
+    // Kept per thread so counting stays out of the way of the other render threads.
+    static TRAVERSAL_COUNTS: Cell<TraversalCounts> = Cell::new(TraversalCounts::default());
+    static TRAVERSAL_NESTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run traverse, which adds its node visits and primitive tests to the counts it is given, and
+/// add those to this thread's counts.
+pub(super) fn counted_traversal<R>(traverse: impl FnOnce(&mut TraversalCounts) -> R) -> R {
+    let nested = TRAVERSAL_NESTED.replace(true);
+    let mut counts = TraversalCounts { rays: if nested { 0 } else { 1 }, ..TraversalCounts::default() };
+    let ret = traverse(&mut counts);
+    TRAVERSAL_NESTED.set(nested);
+    TRAVERSAL_COUNTS.with(|total| {
+        let mut sum = total.get();
+        sum.merge(&counts);
+        total.set(sum);
+    });
+    ret
+}
+
+/// The traversal counts of this thread since the last call, which resets them.
+pub fn take_traversal_counts() -> TraversalCounts { TRAVERSAL_COUNTS.take() }
+
+/// A BVH built with the binned surface area heuristic and stored as a depth-first array of nodes.
+pub struct SahBvh {
+    objects: Vec<Arc<dyn Hit + Send + Sync>>,
+    nodes: Vec<LinearNode>,
+    statistics: BvhStatistics
+}
+
+struct BuildPrimitive {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point
+}
+
+#[derive(Copy, Clone)]
+struct Bucket {
+    count: usize,
+    bbox: Aabb
+}
+
+pub fn sah_bvh(list: HittableList) -> SahBvh {
+    let start_time = Instant::now();
+    let mut primitives: Vec<BuildPrimitive> = list.objects.iter().enumerate().map(|(index, object)| {
+        let bbox = object.bounding_box();
+        BuildPrimitive { index, bbox, centroid: bbox.centroid() }
+    }).collect();
+
+    let mut nodes = Vec::with_capacity(2 * primitives.len().max(1));
+    let mut max_depth = 0;
+    if !primitives.is_empty() {
+        build(&mut primitives, 0, &mut nodes, 1, &mut max_depth);
+    }
+    let objects = primitives.iter().map(|prim| list.objects[prim.index].clone()).collect();
+    let leaf_count = nodes.iter().filter(|node| node.count > 0).count();
+
+    SahBvh {
+        objects,
+        statistics: BvhStatistics {
+            build_time: start_time.elapsed(),
+            primitive_count: primitives.len(),
+            node_count: nodes.len(),
+            leaf_count,
+            max_depth
+        },
+        nodes
+    }
+}
+
+fn make_leaf(nodes: &mut Vec<LinearNode>, bbox: Aabb, offset: usize, count: usize) {
+    nodes.push(LinearNode { bbox, offset, count, axis: 0 });
+}
+
+/// Recursively build the subtree for `primitives`, whose first element sits at `offset` in the
+/// final primitive order, and append its nodes to `nodes` in depth-first order. Subtrees reaching
+/// BVH_MAX_DEPTH become leaves, however many primitives are left, so traversal never needs a
+/// deeper stack.
+fn build(primitives: &mut [BuildPrimitive], offset: usize, nodes: &mut Vec<LinearNode>, depth: usize, max_depth: &mut usize) {
+    *max_depth = (*max_depth).max(depth);
+    let mut bbox = empty_aabb();
+    let mut centroid_bounds = empty_aabb();
+    for prim in primitives.iter() {
+        bbox = aabb_union(&bbox, &prim.bbox);
+        centroid_bounds = aabb_union(&centroid_bounds, &aabb_points(prim.centroid, prim.centroid));
+    }
+    let count = primitives.len();
+    if count == 1 || depth >= BVH_MAX_DEPTH {
+        make_leaf(nodes, bbox, offset, count);
+        return;
+    }
+
+    // Find the cheapest bucket boundary over all three axes.
+    let mut best: Option<(i32, usize, f64)> = None;
+    for axis in 0..3 {
+        let extent = centroid_bounds.axis(axis);
+        if extent.size() <= 0.0 {
+            continue;
+        }
+        let mut buckets = [Bucket { count: 0, bbox: empty_aabb() }; SAH_BUCKETS];
+        for prim in primitives.iter() {
+            let b = bucket_index(prim.centroid[axis], &extent);
+            buckets[b].count += 1;
+            buckets[b].bbox = aabb_union(&buckets[b].bbox, &prim.bbox);
+        }
+        // Sweep from both sides so every split costs O(1).
+        let mut below_area = [0.0; SAH_BUCKETS];
+        let mut below_count = [0usize; SAH_BUCKETS];
+        let mut acc_box = empty_aabb();
+        let mut acc_count = 0;
+        for i in 0..SAH_BUCKETS - 1 {
+            acc_box = aabb_union(&acc_box, &buckets[i].bbox);
+            acc_count += buckets[i].count;
+            below_area[i] = acc_box.surface_area();
+            below_count[i] = acc_count;
+        }
+        let mut acc_box = empty_aabb();
+        let mut acc_count = 0;
+        for i in (1..SAH_BUCKETS).rev() {
+            acc_box = aabb_union(&acc_box, &buckets[i].bbox);
+            acc_count += buckets[i].count;
+            let split = i - 1;
+            let cost = below_count[split] as f64 * below_area[split] + acc_count as f64 * acc_box.surface_area();
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, split, cost));
+            }
+        }
+    }
+
+    let area = bbox.surface_area();
+    let split = match best {
+        Some((axis, split, cost)) => {
+            let cost = SAH_TRAVERSAL_COST + if area > 0.0 { cost / area } else { 0.0 };
+            if count <= MAX_PRIMS_IN_LEAF && cost >= count as f64 { None } else { Some((axis, split)) }
+        }
+        None => None // All centroids coincide, no split can separate them
+    };
+
+    let (axis, mid) = match split {
+        Some((axis, split)) => {
+            let extent = centroid_bounds.axis(axis);
+            let mid = partition(primitives, |prim| bucket_index(prim.centroid[axis], &extent) <= split);
+            (axis, mid)
+        }
+        None if count <= MAX_PRIMS_IN_LEAF => {
+            make_leaf(nodes, bbox, offset, count);
+            return;
+        }
+        None => {
+            // Too many coincident primitives for one leaf, split them in half.
+            (bbox.longest_axis(), count / 2)
+        }
+    };
+
+    let node_index = nodes.len();
+    nodes.push(LinearNode { bbox, offset: 0, count: 0, axis });
+    let (left, right) = primitives.split_at_mut(mid);
+    build(left, offset, nodes, depth + 1, max_depth);
+    nodes[node_index].offset = nodes.len();
+    build(right, offset + mid, nodes, depth + 1, max_depth);
+}
+
+fn bucket_index(centroid: f64, extent: &Interval) -> usize {
+    let b = ((centroid - extent.min) / extent.size() * SAH_BUCKETS as f64) as usize;
+    b.min(SAH_BUCKETS - 1)
+}
+
+/// Reorder so that all elements satisfying pred come first, returning how many do.
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], pred: F) -> usize {
+    let mut first = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(first, i);
+            first += 1;
+        }
+    }
+    first
+}
+
+impl SahBvh {
+    pub fn statistics(&self) -> &BvhStatistics { &self.statistics }
+    /// Build statistics. Traversal is counted per render thread, see take_traversal_counts.
+    pub fn print_statistics(&self) {
+        let stats = &self.statistics;
+        println!("SAH BVH: {} primitives, {} nodes ({} leaves), depth {}, built in {}ms.",
+                 stats.primitive_count, stats.node_count, stats.leaf_count, stats.max_depth, stats.build_time.as_millis());
+    }
+    fn closest_hit(&self, r: &Ray, ray_t: Interval, counts: &mut TraversalCounts) -> Option<HitRecord> {
+        let dir_is_neg = [r.direction().x() < 0.0, r.direction().y() < 0.0, r.direction().z() < 0.0];
+        let mut hit_rec = None;
+        let mut closest_so_far = ray_t.max;
+        // Each level pushes at most one node and build stops at BVH_MAX_DEPTH levels.
+        let mut stack = [0usize; BVH_MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            counts.node_visits += 1;
+            if node.bbox.hit(r, interval(ray_t.min, closest_so_far)) {
+                if node.count > 0 {
+                    for object in &self.objects[node.offset..node.offset + node.count] {
+                        counts.primitive_tests += 1;
+                        if let Some(rec) = object.hit(r, interval(ray_t.min, closest_so_far)) {
+                            closest_so_far = rec.t;
+                            hit_rec = Some(rec);
+                        }
+                    }
+                } else if dir_is_neg[node.axis as usize] {
+                    // Along a negative direction the second child is the nearer one, visit it first.
+                    stack[stack_size] = current + 1;
+                    stack_size += 1;
+                    current = node.offset;
+                    continue;
+                } else {
+                    stack[stack_size] = node.offset;
+                    stack_size += 1;
+                    current += 1;
+                    continue;
+                }
+            }
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+        hit_rec
+    }
+}
+
+impl Hit for SahBvh {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        counted_traversal(|counts| self.closest_hit(r, ray_t, counts))
+    }
+    fn bounding_box(&self) -> Aabb {
+        if self.nodes.is_empty() { empty_aabb() } else { self.nodes[0].bbox }
+    }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) {
+        for object in &self.objects {
+            object.for_each_material(f);
+        }
+    }
+    fn transmittance(&self, r: &Ray, ray_t: Interval) -> f64 {
+        // Every primitive along the segment attenuates, so no child ordering or early exit by distance.
+        let mut ret = 1.0;
+        if self.nodes.is_empty() {
+            return ret;
+        }
+        let mut stack = [0usize; BVH_MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit(r, ray_t) {
+                if node.count > 0 {
+                    for object in &self.objects[node.offset..node.offset + node.count] {
+                        ret *= object.transmittance(r, ray_t);
+                        if ret == 0.0 {
+                            return 0.0;
+                        }
+                    }
+                } else {
+                    stack[stack_size] = node.offset;
+                    stack_size += 1;
+                    current += 1;
+                    continue;
+                }
+            }
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::{color, point, ray, vec};
+    use crate::constants::INFINITY;
+    use crate::hittable::{bvh_node, empty_hittable_list, sphere};
+    use crate::material::lambertian::lambertian;
+
+    #[test]
+    fn finds_the_same_closest_hits_as_the_list() {
+        let mat = Arc::new(lambertian(color(0.5, 0.5, 0.5)));
+        let mut list = empty_hittable_list();
+        for i in 0..200 {
+            let x = (i % 10) as f64 - 4.5;
+            let y = (i / 10 % 5) as f64 * 0.7 - 1.4;
+            let z = (i / 50) as f64 * 1.3 - 2.0;
+            list.add(Arc::new(sphere(point(x, y, z), 0.2 + 0.05 * (i % 7) as f64, mat.clone())));
+        }
+        let bvh = sah_bvh(list.clone());
+        let tree = bvh_node(list.clone());
+        let mut hits = 0;
+        for i in 0..500 {
+            let angle = i as f64 * 0.37;
+            let origin = point(8.0 * angle.cos(), 3.0 * (0.11 * i as f64).sin(), 8.0 * angle.sin());
+            let target = point((i % 13) as f64 * 0.7 - 4.2, (i % 5) as f64 * 0.6 - 1.2, (i % 9) as f64 * 0.5 - 2.0);
+            let r = ray(origin, target - origin, 0.0);
+            let expected = list.hit(&r, interval(0.001, INFINITY)).map(|rec| rec.t);
+            assert_eq!(bvh.hit(&r, interval(0.001, INFINITY)).map(|rec| rec.t), expected);
+            assert_eq!(tree.hit(&r, interval(0.001, INFINITY)).map(|rec| rec.t), expected);
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 250, "only {} of the rays hit anything", hits);
+        assert!(bvh.statistics().max_depth <= BVH_MAX_DEPTH);
+        let missed = ray(point(0.0, 50.0, 0.0), vec(0.0, 1.0, 0.0), 0.0);
+        assert!(bvh.hit(&missed, interval(0.001, INFINITY)).is_none());
+        // Nested BVH nodes add their work to the ray that entered the tree rather than counting new rays.
+        let counts = take_traversal_counts();
+        assert_eq!(counts.rays, 3 * 500 + 1);
+        assert!(counts.primitive_tests < 3 * 200 * 500);
+    }
+}
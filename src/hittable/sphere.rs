@@ -2,13 +2,16 @@ use std::sync::Arc;
 use crate::material::{lambertian, Scatter};
 use super::{Hit, empty_record, HitRecord};
 use super::super::basic::*;
+use crate::constants::{INFINITY, PI, random_double};
+use crate::pdf::local_to_world;
 
 pub struct Sphere {
     center: Point,
     radius: f64,
     mat: Arc<dyn Scatter + Sync + Send>,
     is_moving: bool,
-    center_vec: Vec
+    center_vec: Vec,
+    bbox: Aabb
 }
 
 pub fn empty_sphere() -> Sphere {
@@ -17,30 +20,47 @@ pub fn empty_sphere() -> Sphere {
         radius: 0.0,
         mat: Arc::new(lambertian::empty_lambertian()),
         is_moving: false,
-        center_vec: empty_vec()
+        center_vec: empty_vec(),
+        bbox: empty_aabb()
     }
 }
 
 pub fn sphere(center: Point, radius: f64, mat: Arc<dyn Scatter + Sync + Send>) -> Sphere {
-    Sphere { center, radius, mat, is_moving: false, center_vec: empty_vec() }
+    let rvec = vec(radius, radius, radius);
+    let bbox = aabb_points(center - rvec, center + rvec);
+    Sphere { center, radius, mat, is_moving: false, center_vec: empty_vec(), bbox }
 }
 
+/// A sphere moving from center1 at time 0 to center2 at time 1, resting there before and after.
 pub fn moving_sphere(center1: Point, center2: Point, radius: f64, mat: Arc<dyn Scatter + Sync + Send>) -> Sphere {
+    let rvec = vec(radius, radius, radius);
+    let box1 = aabb_points(center1 - rvec, center1 + rvec);
+    let box2 = aabb_points(center2 - rvec, center2 + rvec);
     Sphere {
         center: center1,
         radius,
         mat,
         is_moving: true,
-        center_vec: center2 - center1
+        center_vec: center2 - center1,
+        bbox: aabb_union(&box1, &box2)
     }
 }
 
 impl Sphere {
     pub fn center(&self, time: f64) -> Point {
-        self.center + time * self.center_vec
+        // Clamped like keyframed animation, so the bounding box holds for any shutter interval.
+        self.center + time.clamp(0.0, 1.0) * self.center_vec
     }
 }
 
+/// Spherical coordinates of a point p on the unit sphere around the origin: u is the angle around
+/// the Y axis starting from X = -1, v the angle from Y = -1 to Y = +1, both scaled to [0, 1].
+pub fn sphere_uv(p: &Point) -> (f64, f64) {
+    let theta = (-p.y()).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
 impl Hit for Sphere {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
         let center = if self.is_moving { self.center(r.time()) } else { self.center };
@@ -67,7 +87,69 @@ impl Hit for Sphere {
         rec.p = r.at(root);
         let outward_normal = (rec.p - center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
+        (rec.u, rec.v) = sphere_uv(&outward_normal);
         rec.mat = self.mat.clone();
         Some(rec)
     }
-}
\ No newline at end of file
+    fn bounding_box(&self) -> Aabb { self.bbox }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { f(&self.mat) }
+    fn samples_directions(&self) -> bool { true }
+    /// Uniform over the cone of directions the sphere subtends at time, or over all directions from inside.
+    fn pdf_value(&self, origin: &Point, direction: &Vec, time: f64) -> f64 {
+        let distance_squared = (self.center(time) - *origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+        if self.hit(&ray(*origin, *direction, time), interval(0.001, INFINITY)).is_none() {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+    fn random(&self, origin: &Point, time: f64) -> Vec {
+        let direction = self.center(time) - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return rand_unit_vec();
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let z = 1.0 + random_double() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random_double();
+        let sin_theta = (1.0 - z * z).sqrt();
+        local_to_world(&direction.unit(), phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+    }
+    fn sample_surface(&self, time: f64) -> Option<(HitRecord, f64)> {
+        let normal = rand_unit_vec();
+        let mut rec = empty_record();
+        rec.p = self.center(time) + self.radius * normal;
+        rec.normal = normal;
+        rec.geometric_normal = normal;
+        rec.front_face = true;
+        (rec.u, rec.v) = sphere_uv(&normal);
+        rec.mat = self.mat.clone();
+        Some((rec, 1.0 / (4.0 * PI * self.radius * self.radius)))
+    }
+    fn surface_pdf(&self, p: &Point, _normal: &Vec, time: f64) -> f64 {
+        let on_surface = ((*p - self.center(time)).length() - self.radius).abs() < 1e-6 * (1.0 + self.radius);
+        if on_surface { 1.0 / (4.0 * PI * self.radius * self.radius) } else { 0.0 }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::diffuse_light::diffuse_light;
+
+    #[test]
+    fn samples_moving_lights_where_they_are_at_the_ray_time() {
+        let light = moving_sphere(point(0.0, 0.0, 0.0), point(10.0, 0.0, 0.0), 1.0, Arc::new(diffuse_light(color(1.0, 1.0, 1.0))));
+        let origin = point(5.0, 10.0, 0.0);
+        for time in [0.0, 0.5, 1.0] {
+            let direction = light.random(&origin, time);
+            assert!(light.hit(&ray(origin, direction, time), interval(0.001, INFINITY)).is_some());
+            assert!(light.pdf_value(&origin, &direction, time) > 0.0);
+        }
+        let towards_start = point(0.0, 0.0, 0.0) - origin;
+        assert!(light.pdf_value(&origin, &towards_start, 0.0) > 0.0);
+        assert_eq!(light.pdf_value(&origin, &towards_start, 1.0), 0.0);
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use crate::material::Scatter;
+use super::{Hit, empty_record, HitRecord};
+use super::super::basic::*;
+
+pub struct Triangle {
+    vertices: [Point; 3],
+    normals: Option<[Vec; 3]>, // Per-vertex shading normals, the face normal is used if absent
+    uvs: [(f64, f64); 3],
+    two_sided: bool,
+    mat: Arc<dyn Scatter + Sync + Send>,
+    bbox: Aabb
+}
+
+/// A flat-shaded, two-sided triangle with the default UV layout (0, 0), (1, 0), (0, 1).
+pub fn triangle(v0: Point, v1: Point, v2: Point, mat: Arc<dyn Scatter + Sync + Send>) -> Triangle {
+    Triangle {
+        vertices: [v0, v1, v2],
+        normals: None,
+        uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+        two_sided: true,
+        mat,
+        bbox: triangle_bbox(&v0, &v1, &v2)
+    }
+}
+
+/// A triangle with per-vertex normals and UVs. A one-sided triangle can only be hit from the side
+/// its vertices appear counter-clockwise from.
+pub fn smooth_triangle(vertices: [Point; 3], normals: [Vec; 3], uvs: [(f64, f64); 3], two_sided: bool,
+                       mat: Arc<dyn Scatter + Sync + Send>) -> Triangle {
+    Triangle {
+        vertices,
+        normals: Some(normals),
+        uvs,
+        two_sided,
+        mat,
+        bbox: triangle_bbox(&vertices[0], &vertices[1], &vertices[2])
+    }
+}
+
+pub(super) fn triangle_bbox(v0: &Point, v1: &Point, v2: &Point) -> Aabb {
+    aabb_union(&aabb_points(*v0, *v1), &aabb_points(*v2, *v2)).padded(1e-4)
+}
+
+/// Möller–Trumbore ray/triangle intersection. Returns t and the barycentric weights (b1, b2)
+/// of v1 and v2.
+pub(super) fn moller_trumbore(r: &Ray, ray_t: Interval, v0: &Point, v1: &Point, v2: &Point, two_sided: bool) -> Option<(f64, f64, f64)> {
+    let edge1 = *v1 - *v0;
+    let edge2 = *v2 - *v0;
+    let pvec = cross(r.direction(), &edge2);
+    let det = dot(&edge1, &pvec);
+    // det < 0 means the ray comes from the back side.
+    if (two_sided && det.abs() < 1e-12) || (!two_sided && det < 1e-12) {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = *r.origin() - *v0;
+    let b1 = dot(&tvec, &pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = cross(&tvec, &edge1);
+    let b2 = dot(r.direction(), &qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = dot(&edge2, &qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+/// Fill a hit record from the barycentric coordinates of a triangle hit. The shading normal is
+/// flipped to the side of the geometric normal so that front_face stays consistent.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn triangle_record(r: &Ray, t: f64, b1: f64, b2: f64, vertices: &[Point; 3], normals: Option<&[Vec; 3]>,
+                              uvs: &[(f64, f64); 3], mat: &Arc<dyn Scatter + Sync + Send>) -> HitRecord {
+    let b0 = 1.0 - b1 - b2;
+    let mut rec = empty_record();
+    rec.t = t;
+    rec.p = r.at(t);
+    let geometric_normal = cross(&(vertices[1] - vertices[0]), &(vertices[2] - vertices[0])).unit();
+    rec.set_face_normal(r, &geometric_normal);
+    if let Some(n) = normals {
+        let mut shading_normal = (b0 * n[0] + b1 * n[1] + b2 * n[2]).unit();
+        if dot(&shading_normal, &geometric_normal) < 0.0 {
+            shading_normal = -shading_normal;
+        }
+        rec.normal = if rec.front_face { shading_normal } else { -shading_normal };
+    }
+    rec.u = b0 * uvs[0].0 + b1 * uvs[1].0 + b2 * uvs[2].0;
+    rec.v = b0 * uvs[0].1 + b1 * uvs[1].1 + b2 * uvs[2].1;
+    rec.mat = mat.clone();
+    rec
+}
+
+impl Hit for Triangle {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let [v0, v1, v2] = &self.vertices;
+        let (t, b1, b2) = moller_trumbore(r, ray_t, v0, v1, v2, self.two_sided)?;
+        Some(triangle_record(r, t, b1, b2, &self.vertices, self.normals.as_ref(), &self.uvs, &self.mat))
+    }
+    fn bounding_box(&self) -> Aabb { self.bbox }
+    fn for_each_material(&self, f: &mut dyn FnMut(&Arc<dyn Scatter + Sync + Send>)) { f(&self.mat) }
+}
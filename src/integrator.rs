@@ -0,0 +1,226 @@
+pub mod path;
+pub mod bdpt;
+pub mod sppm;
+pub mod mlt;
+pub mod debug;
+
+pub use path::*;
+pub use bdpt::*;
+pub use sppm::*;
+pub use mlt::*;
+pub use debug::*;
+
+use std::sync::Arc;
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::basic::*;
+use crate::film::{LightGroup, SampleAovs};
+use crate::constants::{AO_RADIUS, command_line_option, DEPTH_VIEW_FAR, INFINITY, random_double, SURFACE_EPSILON};
+use crate::hittable::{Hit, HitRecord, take_traversal_counts, TraversalCounts};
+use crate::material::Scatter;
+use crate::pdf::{background_pdf, cosine_pdf, hittable_pdf, mixture_pdf, Pdf, power_heuristic};
+
+/// What an integrator sees of the scene while rendering, borrowed once per thread.
+pub struct Scene<'a> {
+    pub camera: &'a Camera,
+    pub world: &'a (dyn Hit + Send + Sync),
+    pub lights: Option<&'a (dyn Hit + Send + Sync)>,
+    pub background: &'a (dyn Background + Sync + Send),
+    pub background_sampled: bool, // Whether the background can be importance sampled
+    pub light_groups: &'a [LightGroup]
+}
+
+/// A ray leaving a point of the lights, as sampled by Scene::sample_emission.
+pub struct LightEmission {
+    pub rec: HitRecord,
+    pub ray: Ray,
+    pub pdf_pos: f64, // Area density of the point
+    pub pdf_dir: f64, // Solid angle density of the direction
+    pub le: Color
+}
+
+impl LightEmission {
+    /// Throughput of the emitted ray: radiance times cosine over the densities.
+    pub fn beta(&self) -> Color {
+        dot(&self.rec.normal, &self.ray.direction().unit()).abs() / (self.pdf_pos * self.pdf_dir) * self.le
+    }
+}
+
+impl Scene<'_> {
+    /// Run f with the distribution for sampling the lights as they are at time and, if it
+    /// supports importance sampling, the background from origin. None if nothing can be sampled.
+    pub fn with_light_pdf<R>(&self, origin: &Point, time: f64, f: impl FnOnce(&dyn Pdf) -> R) -> Option<R> {
+        let lights = self.lights.map(|lights| hittable_pdf(lights, *origin, time));
+        let background = background_pdf(self.background);
+        match (&lights, self.background_sampled) {
+            (Some(lights), true) => Some(f(&mixture_pdf(lights, &background, 0.5))),
+            (Some(lights), false) => Some(f(lights)),
+            (None, true) => Some(f(&background)),
+            (None, false) => None
+        }
+    }
+    /// Index of the light group an emitter with material mat belongs to.
+    pub fn light_group(&self, mat: &Arc<dyn Scatter + Sync + Send>) -> Option<usize> {
+        self.light_groups.iter().position(|group| group.contains(mat))
+    }
+    /// Radiance reaching the start of a shadow ray from the first light along it, or from the
+    /// background, attenuated by whatever lies in between. Also returns the light's group.
+    pub fn incoming(&self, shadow: &Ray, t_min: f64) -> (Color, Option<usize>) {
+        if let Some(light) = self.lights.and_then(|lights| lights.hit(shadow, interval(t_min, INFINITY))) {
+            let transmittance = self.world.transmittance(shadow, interval(t_min, (light.t - SURFACE_EPSILON).max(t_min)));
+            return (transmittance * light.emitted(), self.light_group(&light.mat));
+        }
+        (self.world.transmittance(shadow, interval(t_min, INFINITY)) * self.background.value(shadow), None)
+    }
+    /// Light emitted at hit or, if the ray escaped, by the background, found by following a
+    /// direction that the previous vertex's material and light sampling would have chosen with
+    /// densities mis. Weighted against the light sample taken there, unless mis.0 is zero, for
+    /// camera rays and specular bounces, or the emitter lies inside a medium, where it is never
+    /// light sampled.
+    pub fn weighted_emission(&self, r: &Ray, hit: Option<&HitRecord>, mis: (f64, f64)) -> Color {
+        let weight = if mis.0 > 0.0 { power_heuristic(mis.0, mis.1) } else { 1.0 };
+        match hit {
+            Some(hit) if hit.volume => hit.emitted(),
+            Some(hit) => weight * hit.emitted(),
+            None => weight * self.background.value(r)
+        }
+    }
+    /// A ray of light leaving a random point of the lights at time. Lights emit from both sides,
+    /// so the direction is cosine distributed about a random one. None without lights to sample.
+    pub fn sample_emission(&self, time: f64) -> Option<LightEmission> {
+        let (rec, pdf_pos) = self.lights?.sample_surface(time)?;
+        let side = if random_double() < 0.5 { rec.normal } else { -rec.normal };
+        let emission = cosine_pdf(&side);
+        let direction = emission.generate();
+        let pdf_dir = 0.5 * emission.value(&direction);
+        if pdf_pos <= 0.0 || pdf_dir <= 0.0 {
+            return None;
+        }
+        let le = rec.emitted();
+        Some(LightEmission { ray: ray(rec.p, direction, time), rec, pdf_pos, pdf_dir, le })
+    }
+    /// One light sample of the light scattered at rec towards r_in, weighted against the
+    /// material sampling the same direction, and the group of the light it reached. Shadow rays
+    /// start at t_min.
+    pub fn sample_direct(&self, r_in: &Ray, rec: &HitRecord, t_min: f64) -> (Color, Option<usize>) {
+        self.with_light_pdf(&rec.p, r_in.time(), |pdf| {
+            let direction = pdf.generate().unit();
+            let light_pdf = pdf.value(&direction);
+            let shadow = ray(rec.p, direction, r_in.time());
+            let (value, bsdf_pdf) = rec.mat.eval(r_in, rec, &shadow);
+            if light_pdf <= 0.0 || bsdf_pdf <= 0.0 {
+                return (black(), None);
+            }
+            let (incoming, group) = self.incoming(&shadow, t_min);
+            (power_heuristic(light_pdf, bsdf_pdf) / light_pdf * value * incoming, group)
+        }).unwrap_or((black(), None))
+    }
+}
+
+/// Counters gathered by each render thread and summed at the end.
+#[derive(Copy, Clone, Default)]
+pub struct RenderStatistics {
+    pub camera_rays: u64,
+    pub segments: u64, // Rays traced along paths, camera rays included
+    pub traversal: TraversalCounts
+}
+
+impl RenderStatistics {
+    pub fn merge(&mut self, other: &RenderStatistics) {
+        self.camera_rays += other.camera_rays;
+        self.segments += other.segments;
+        self.traversal.merge(&other.traversal);
+    }
+    /// Move the BVH traversal counts of the calling thread in, once its share of the work is done.
+    pub fn collect_traversal(&mut self) {
+        self.traversal.merge(&take_traversal_counts());
+    }
+    pub fn average_path_length(&self) -> f64 {
+        if self.camera_rays == 0 { 0.0 } else { self.segments as f64 / self.camera_rays as f64 }
+    }
+}
+
+/// Light deposited on arbitrary pixels, by paths that reach the camera from the light side.
+/// Each render thread keeps its own and they are summed into the image at the end.
+pub struct Splats {
+    width: i32,
+    pixels: std::vec::Vec<Color>
+}
+
+pub fn splats(width: i32, height: i32) -> Splats {
+    Splats { width, pixels: vec![black(); (width * height) as usize] }
+}
+
+impl Splats {
+    /// Add a contribution, scaled like one camera sample of that pixel.
+    pub fn add(&mut self, pixel: (i32, i32), color: Color) {
+        self.pixels[(pixel.1 * self.width + pixel.0) as usize] += color;
+    }
+    pub fn pixels(&self) -> &[Color] { &self.pixels }
+}
+
+/// Estimates the light arriving at the camera. Implementations are shared by all render threads.
+pub trait Integrator {
+    /// Radiance arriving along the camera ray r. Integrators tracing from the lights may also
+    /// deposit light on other pixels through splats, those that support AOVs fill in aovs, which
+    /// arrives cleared.
+    fn li(&self, r: &Ray, scene: &Scene, stats: &mut RenderStatistics, splats: &mut Splats, aovs: &mut SampleAovs) -> Color;
+    /// Integrators that need the whole image at once, like photon mapping, render it here and
+    /// return each pixel's sum of samples_per_pixel estimates, row by row. None renders pixel
+    /// by pixel with li.
+    fn render_image(&self, _scene: &Scene, _samples_per_pixel: i32, _stats: &mut RenderStatistics) -> Option<std::vec::Vec<Color>> {
+        None
+    }
+    /// Whether li fills in the AOVs. Light splatted onto other pixels is not split by bounce, so
+    /// integrators that splat leave them out rather than break direct plus indirect being beauty.
+    fn supports_aovs(&self) -> bool {
+        false
+    }
+}
+
+/// Names accepted by integrator_by_name, a number after a colon sets the radius of "ao", the
+/// far distance of "depth" and the seed of "mlt". A seeded Metropolis render only repeats if the
+/// scene is built the same way every time, the random spheres of main.rs are drawn afresh on
+/// every run.
+pub const INTEGRATOR_NAMES: [&str; 11] = [
+    "path", "bdpt", "sppm", "mlt[:seed]", "ao[:radius]", "shading-normals", "geometric-normals", "front-face", "albedo",
+    "material-id", "depth[:far]"
+];
+
+/// The integrator called name, e.g. "bdpt", "ao:0.5" or "mlt:7".
+pub fn integrator_by_name(name: &str) -> Result<Arc<dyn Integrator + Send + Sync>, String> {
+    let (kind, parameter) = match name.split_once(':') {
+        Some((kind, value)) => (kind, Some(value)),
+        None => (name, None)
+    };
+    let number = |value: &str| value.parse::<f64>().map_err(|_| format!("'{}' is not a number", value));
+    let ret: Arc<dyn Integrator + Send + Sync> = match (kind, parameter) {
+        ("path", None) => Arc::new(path_tracer()),
+        ("bdpt", None) => Arc::new(bidirectional_path_tracer()),
+        ("sppm", None) => Arc::new(progressive_photon_mapper()),
+        ("mlt", None) => Arc::new(metropolis()),
+        ("mlt", Some(seed)) => Arc::new(metropolis_seeded(seed.parse::<u64>().map_err(|_| format!("'{}' is not a seed", seed))?)),
+        ("ao", radius) => Arc::new(ambient_occlusion(radius.map(number).transpose()?.unwrap_or(AO_RADIUS))),
+        ("shading-normals", None) => Arc::new(debug_integrator(DebugView::ShadingNormals)),
+        ("geometric-normals", None) => Arc::new(debug_integrator(DebugView::GeometricNormals)),
+        ("front-face", None) => Arc::new(debug_integrator(DebugView::FrontFace)),
+        ("albedo", None) => Arc::new(debug_integrator(DebugView::Albedo)),
+        ("material-id", None) => Arc::new(debug_integrator(DebugView::MaterialId)),
+        ("depth", far) => Arc::new(debug_integrator(DebugView::Depth(far.map(number).transpose()?.unwrap_or(DEPTH_VIEW_FAR)))),
+        _ => return Err(format!("unknown integrator '{}', expected one of {}", name, INTEGRATOR_NAMES.join(", ")))
+    };
+    Ok(ret)
+}
+
+/// The integrator chosen with "--integrator <name>" on the command line, if any. Exits with a
+/// message if the name is not understood.
+pub fn integrator_from_args() -> Option<Arc<dyn Integrator + Send + Sync>> {
+    let name = command_line_option("--integrator")?;
+    match integrator_by_name(&name) {
+        Ok(integrator) => Some(integrator),
+        Err(message) => {
+            eprintln!("Error: {}.", message);
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use crate::basic::*;
+use crate::constants::{BDPT_MAX_DEPTH, INFINITY, PI, SURFACE_EPSILON, VOLUME_EPSILON};
+use crate::hittable::HitRecord;
+use crate::film::SampleAovs;
+use super::{Integrator, RenderStatistics, Scene, Splats};
+
+/// Bidirectional path tracing after Veach. Every camera sample traces a subpath from the camera
+/// and one from a light, then joins each prefix of the one to each prefix of the other. All
+/// ways of building the same path are weighted with the power heuristic, so caustics found from
+/// the light side and diffuse light found from the camera side both come out clean. Paths that
+/// hit the camera from the light side are splatted onto the pixel they land on.
+/// Only the lights given to the camera start light subpaths, the background is reached from the
+/// camera side alone.
+pub struct BidirectionalPathTracer {
+    max_depth: i32
+}
+
+pub fn bidirectional_path_tracer() -> BidirectionalPathTracer { BidirectionalPathTracer { max_depth: BDPT_MAX_DEPTH } }
+
+pub fn bidirectional_path_tracer_depth(max_depth: i32) -> BidirectionalPathTracer { BidirectionalPathTracer { max_depth } }
+
+#[derive(Copy, Clone, PartialEq)]
+enum VertexKind {
+    Camera,
+    Light,
+    Surface,
+    Medium
+}
+
+#[derive(Clone)]
+struct Vertex {
+    kind: VertexKind,
+    p: Point,
+    normal: Vec,
+    rec: Option<HitRecord>, // Surface, medium and light vertices
+    r_in: Ray, // The ray that reached the vertex along its own subpath
+    beta: Color, // Throughput of the subpath up to, not including, this vertex's scattering
+    pdf_fwd: f64, // Area density of reaching the vertex from its own subpath
+    pdf_rev: f64, // Area density of reaching it from the other direction
+    delta: bool // Specular: cannot be joined to anything
+}
+
+fn vertex(kind: VertexKind, p: Point, normal: Vec, rec: Option<HitRecord>, r_in: Ray, beta: Color) -> Vertex {
+    Vertex { kind, p, normal, rec, r_in, beta, pdf_fwd: 0.0, pdf_rev: 0.0, delta: false }
+}
+
+impl Vertex {
+    fn on_surface(&self) -> bool { matches!(self.kind, VertexKind::Surface | VertexKind::Light) }
+    fn epsilon(&self) -> f64 { if self.kind == VertexKind::Medium { VOLUME_EPSILON } else { SURFACE_EPSILON } }
+    /// Turn a solid angle density at this vertex into an area density at next.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.p - self.p;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let mut ret = pdf / distance_squared;
+        if next.on_surface() {
+            ret *= dot(&next.normal, &w.unit()).abs();
+        }
+        ret
+    }
+    /// BSDF or phase function times cosine towards next, for light that arrived along r_in.
+    fn f(&self, next: &Vertex) -> Color {
+        match &self.rec {
+            Some(rec) => (*rec.mat).eval(&self.r_in, rec, &ray(self.p, next.p - self.p, self.r_in.time())).0,
+            None => black()
+        }
+    }
+    /// Area density at next of this vertex scattering towards it, having been reached from prev.
+    fn pdf(&self, scene: &Scene, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let time = self.r_in.time();
+        let pdf = match self.kind {
+            VertexKind::Camera => scene.camera.pdf_direction(&ray(self.p, next.p - self.p, time)),
+            VertexKind::Light => return self.pdf_light(next),
+            VertexKind::Surface | VertexKind::Medium => {
+                let Some(rec) = &self.rec else { return 0.0; };
+                let r_in = match prev {
+                    Some(prev) => ray(prev.p, self.p - prev.p, time),
+                    None => self.r_in
+                };
+                (*rec.mat).eval(&r_in, rec, &ray(self.p, next.p - self.p, time)).1
+            }
+        };
+        self.convert_density(pdf, next)
+    }
+    /// Area density at next of emission from this point of a light, which emits from both sides.
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let cos_theta = dot(&self.normal, &(next.p - self.p).unit()).abs();
+        self.convert_density(cos_theta / (2.0 * PI), next)
+    }
+    /// Area density of a light subpath starting here.
+    fn pdf_light_origin(&self, scene: &Scene) -> f64 {
+        scene.lights.map_or(0.0, |lights| lights.surface_pdf(&self.p, &self.normal, self.r_in.time()))
+    }
+    fn emitted(&self) -> Color {
+        match &self.rec {
+            Some(rec) => rec.emitted(),
+            None => black()
+        }
+    }
+}
+
+/// Extend path from r, whose direction was chosen with solid angle density pdf, until it has
+/// max_vertices vertices, leaves the scene or is absorbed. Returns the throughput and ray of a
+/// path that escaped.
+fn random_walk(scene: &Scene, r: Ray, beta: Color, pdf: f64, max_vertices: usize, path: &mut std::vec::Vec<Vertex>,
+               stats: &mut RenderStatistics) -> Option<(Color, Ray)> {
+    let mut current = r;
+    let mut beta = beta;
+    let mut pdf_fwd = pdf;
+    let mut t_min = SURFACE_EPSILON;
+    while path.len() < max_vertices {
+        stats.segments += 1;
+        let Some(rec) = scene.world.hit(&current, interval(t_min, INFINITY)) else {
+            return Some((beta, current));
+        };
+        let kind = if rec.volume { VertexKind::Medium } else { VertexKind::Surface };
+        let mut next = vertex(kind, rec.p, rec.normal, Some(rec.clone()), current, beta);
+        let prev = path.last().expect("Walks start from a camera or light vertex");
+        next.pdf_fwd = prev.convert_density(pdf_fwd, &next);
+        path.push(next);
+
+        let Some(scatter_record) = (*rec.mat).scatter(&current, &rec) else { break; };
+        let scattered = scatter_record.scattered;
+        let time = current.time();
+        let pdf = (*rec.mat).eval(&current, &rec, &scattered).1;
+        let n = path.len();
+        if pdf <= 0.0 {
+            // Specular: no other strategy can produce this bounce, the densities cancel in the weights.
+            path[n - 1].delta = true;
+            pdf_fwd = 0.0;
+        } else {
+            let reverse = (*rec.mat).eval(&ray(rec.p, -*scattered.direction(), time), &rec, &ray(rec.p, -*current.direction(), time)).1;
+            path[n - 2].pdf_rev = path[n - 1].convert_density(reverse, &path[n - 2]);
+            pdf_fwd = pdf;
+        }
+        beta *= scatter_record.attenuation;
+        if is_black(&beta) {
+            break;
+        }
+        t_min = if rec.volume { VOLUME_EPSILON } else { SURFACE_EPSILON };
+        current = scattered;
+    }
+    None
+}
+
+/// Transmittance of the segment between two vertices.
+fn transmittance(scene: &Scene, a: &Vertex, b: &Vertex) -> f64 {
+    let d = b.p - a.p;
+    let distance = d.length();
+    let t_max = distance - b.epsilon();
+    if t_max <= a.epsilon() {
+        return 1.0;
+    }
+    scene.world.transmittance(&ray(a.p, d / distance, a.r_in.time()), interval(a.epsilon(), t_max))
+}
+
+fn is_black(c: &Color) -> bool { c.r() == 0.0 && c.g() == 0.0 && c.b() == 0.0 }
+
+fn remap(pdf: f64) -> f64 { if pdf != 0.0 { pdf } else { 1.0 } }
+
+/// Power heuristic weight of the strategy joining s light and t camera vertices, against all
+/// other ways of sampling the same path. `sampled` replaces the light vertex when s is 1 and the
+/// camera vertex when t is 1, those are sampled afresh rather than taken from the subpaths.
+fn mis_weight(scene: &Scene, light: &[Vertex], camera: &[Vertex], sampled: Option<&Vertex>, s: usize, t: usize) -> f64 {
+    // An emitter seen directly has two strategies, s=0,t=2 and a light point connected to the lens
+    // with s=1,t=1. li never evaluates the latter, so it is left out of every sum: it is the only
+    // other strategy for paths of two vertices, which keep their full weight, and for longer paths
+    // the loops below never reach s'=1,t'=1.
+    if s + t == 2 {
+        return 1.0;
+    }
+    // Densities of the vertices involved, with the endpoints' reverse densities recomputed for
+    // this particular connection.
+    let mut light_pdfs: std::vec::Vec<(f64, f64, bool)> = light[..s].iter().map(|v| (v.pdf_fwd, v.pdf_rev, v.delta)).collect();
+    let mut camera_pdfs: std::vec::Vec<(f64, f64, bool)> = camera[..t].iter().map(|v| (v.pdf_fwd, v.pdf_rev, v.delta)).collect();
+    let qs = if s == 1 { sampled } else if s > 0 { Some(&light[s - 1]) } else { None };
+    let pt = if t == 1 { sampled.expect("Camera connections sample a lens point") } else { &camera[t - 1] };
+    let qs_minus = if s > 1 { Some(&light[s - 2]) } else { None };
+    let pt_minus = if t > 1 { Some(&camera[t - 2]) } else { None };
+    if s == 1 {
+        light_pdfs[0] = (qs.expect("Light vertex is sampled").pdf_fwd, 0.0, false);
+    }
+
+    camera_pdfs[t - 1].2 = false;
+    camera_pdfs[t - 1].1 = match qs {
+        Some(qs) => qs.pdf(scene, qs_minus, pt),
+        None => pt.pdf_light_origin(scene)
+    };
+    if let Some(pt_minus) = pt_minus {
+        camera_pdfs[t - 2].1 = match qs {
+            Some(qs) => pt.pdf(scene, Some(qs), pt_minus),
+            None => pt.pdf_light(pt_minus)
+        };
+    }
+    if let Some(qs) = qs {
+        light_pdfs[s - 1].2 = false;
+        light_pdfs[s - 1].1 = pt.pdf(scene, pt_minus, qs);
+        if let Some(qs_minus) = qs_minus {
+            light_pdfs[s - 2].1 = qs.pdf(scene, Some(pt), qs_minus);
+        }
+    }
+
+    let mut sum = 0.0;
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        let r = remap(camera_pdfs[i].1) / remap(camera_pdfs[i].0);
+        ratio *= r * r;
+        if !camera_pdfs[i].2 && !camera_pdfs[i - 1].2 {
+            sum += ratio;
+        }
+    }
+    ratio = 1.0;
+    for i in (0..s).rev() {
+        let r = remap(light_pdfs[i].1) / remap(light_pdfs[i].0);
+        ratio *= r * r;
+        let delta_before = i > 0 && light_pdfs[i - 1].2;
+        if !light_pdfs[i].2 && !delta_before {
+            sum += ratio;
+        }
+    }
+    1.0 / (1.0 + sum)
+}
+
+impl BidirectionalPathTracer {
+    fn camera_subpath(&self, r: &Ray, scene: &Scene, stats: &mut RenderStatistics) -> (std::vec::Vec<Vertex>, Option<(Color, Ray)>) {
+        let mut path = std::vec::Vec::with_capacity(self.max_depth as usize + 2);
+        path.push(vertex(VertexKind::Camera, *r.origin(), empty_vec(), None, *r, white()));
+        let pdf = scene.camera.pdf_direction(r);
+        let escaped = random_walk(scene, *r, white(), pdf, self.max_depth as usize + 2, &mut path, stats);
+        (path, escaped)
+    }
+    fn light_subpath(&self, scene: &Scene, time: f64, stats: &mut RenderStatistics) -> std::vec::Vec<Vertex> {
+        let mut path = std::vec::Vec::with_capacity(self.max_depth as usize + 1);
+        let Some(emission) = scene.sample_emission(time) else { return path; };
+        if is_black(&emission.le) {
+            return path;
+        }
+        let rec = &emission.rec;
+        let mut start = vertex(VertexKind::Light, rec.p, rec.normal, Some(rec.clone()), emission.ray, emission.le / emission.pdf_pos);
+        start.pdf_fwd = emission.pdf_pos;
+        path.push(start);
+        random_walk(scene, emission.ray, emission.beta(), emission.pdf_dir, self.max_depth as usize + 1, &mut path, stats);
+        path
+    }
+    /// Contribution of joining s light and t camera vertices, with the pixel it belongs to when
+    /// t is 1.
+    fn connect(&self, scene: &Scene, light: &[Vertex], camera: &[Vertex], s: usize, t: usize) -> Option<(Color, Option<(i32, i32)>)> {
+        let time = camera[0].r_in.time();
+        let (contribution, sampled, pixel) = if s == 0 {
+            let pt = &camera[t - 1];
+            let le = pt.emitted();
+            if is_black(&le) {
+                return None;
+            }
+            // Emitters that are not among the lights, including emitting media, have no other strategy.
+            if pt.kind == VertexKind::Medium || pt.pdf_light_origin(scene) == 0.0 {
+                return Some((pt.beta * le, None));
+            }
+            (pt.beta * le, None, None)
+        } else if t == 1 {
+            let qs = &light[s - 1];
+            if qs.delta || !matches!(qs.kind, VertexKind::Surface | VertexKind::Medium) {
+                return None;
+            }
+            let connection = scene.camera.connect(&qs.p, time)?;
+            let lens = vertex(VertexKind::Camera, connection.lens, empty_vec(), None, ray(connection.lens, qs.p - connection.lens, time), white());
+            let distance_squared = (qs.p - lens.p).length_squared();
+            let we = connection.importance * connection.cos_theta / (distance_squared * connection.pdf);
+            let contribution = we * qs.beta * qs.f(&lens);
+            if is_black(&contribution) {
+                return None;
+            }
+            (contribution * transmittance(scene, qs, &lens), Some(lens), Some(connection.pixel))
+        } else if s == 1 {
+            let pt = &camera[t - 1];
+            if pt.delta {
+                return None;
+            }
+            let (rec, pdf_pos) = scene.lights?.sample_surface(time)?;
+            if pdf_pos <= 0.0 {
+                return None;
+            }
+            let le = rec.emitted();
+            let r_in = ray(rec.p, rec.normal, time);
+            let mut light_vertex = vertex(VertexKind::Light, rec.p, rec.normal, Some(rec), r_in, le / pdf_pos);
+            light_vertex.pdf_fwd = pdf_pos;
+            let w = light_vertex.p - pt.p;
+            let cos_light = dot(&light_vertex.normal, &w.unit()).abs();
+            let contribution = cos_light / w.length_squared() * pt.beta * pt.f(&light_vertex) * light_vertex.beta;
+            if is_black(&contribution) {
+                return None;
+            }
+            (contribution * transmittance(scene, pt, &light_vertex), Some(light_vertex), None)
+        } else {
+            let qs = &light[s - 1];
+            let pt = &camera[t - 1];
+            if qs.delta || pt.delta {
+                return None;
+            }
+            let distance_squared = (pt.p - qs.p).length_squared();
+            let contribution = qs.beta * qs.f(pt) * pt.f(qs) * pt.beta / distance_squared;
+            if is_black(&contribution) {
+                return None;
+            }
+            (contribution * transmittance(scene, qs, pt), None, None)
+        };
+        if is_black(&contribution) {
+            return None;
+        }
+        let weight = mis_weight(scene, light, camera, sampled.as_ref(), s, t);
+        Some((weight * contribution, pixel))
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn li(&self, r: &Ray, scene: &Scene, stats: &mut RenderStatistics, splats: &mut Splats, _aovs: &mut SampleAovs) -> Color {
+        stats.camera_rays += 1;
+        let (camera, escaped) = self.camera_subpath(r, scene, stats);
+        let light = self.light_subpath(scene, r.time(), stats);
+        let mut radiance = match escaped {
+            Some((beta, escaped_ray)) => beta * scene.background.value(&escaped_ray),
+            None => black()
+        };
+        for t in 1..=camera.len() {
+            for s in 0..=light.len() {
+                let depth = (s + t) as i32 - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > self.max_depth {
+                    continue;
+                }
+                match self.connect(scene, &light, &camera, s, t) {
+                    Some((contribution, Some(pixel))) => splats.add(pixel, contribution),
+                    Some((contribution, None)) => radiance += contribution,
+                    None => {}
+                }
+            }
+        }
+        radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::background::solid_background;
+    use crate::camera::{camera, get_ray};
+    use crate::constants::random_double;
+    use crate::film::sample_aovs;
+    use crate::hittable::{Hit, hittable_list, quad};
+    use crate::integrator::{path_tracer, splats};
+    use crate::material::{diffuse_light::diffuse_light, lambertian::lambertian};
+
+    #[test]
+    fn agrees_with_path_tracer_on_visible_quad_light() {
+        let cam = camera();
+        let light: Arc<dyn Hit + Send + Sync> = Arc::new(quad(point(0.0, 0.5, -1.0), vec(0.0, 0.0, 2.0), vec(0.0, 1.0, 0.0), Arc::new(diffuse_light(color(4.0, 4.0, 4.0)))));
+        let mut world = hittable_list(light.clone());
+        world.add(Arc::new(quad(point(-10.0, 0.0, -10.0), vec(20.0, 0.0, 0.0), vec(0.0, 0.0, 20.0), Arc::new(lambertian(color(0.5, 0.5, 0.5))))));
+        let lights = hittable_list(light.clone());
+        let background = solid_background(black());
+        let scene = Scene { camera: &cam, world: &world, lights: Some(&lights), background: &background, background_sampled: false, light_groups: &[] };
+        let (width, height) = cam.image_size();
+        let (bdpt, path) = (bidirectional_path_tracer(), path_tracer());
+        let mut stats = RenderStatistics::default();
+        let mut aovs = sample_aovs(0);
+        let mut bdpt_splats = splats(width, height);
+        let mut unused_splats = splats(width, height);
+
+        let samples = 40000;
+        let (mut bdpt_sum, mut path_sum) = (black(), black());
+        for _ in 0..samples {
+            let r = get_ray(&cam, (random_double() * width as f64) as i32, (random_double() * height as f64) as i32);
+            let bdpt_radiance = bdpt.li(&r, &scene, &mut stats, &mut bdpt_splats, &mut aovs);
+            let path_radiance = path.li(&r, &scene, &mut stats, &mut unused_splats, &mut aovs);
+            // Directly seen emitters are only found by s=0,t=2 and must keep their full weight.
+            if world.hit(&r, interval(SURFACE_EPSILON, INFINITY)).is_some_and(|rec| rec.p.x().abs() < 1e-6) {
+                assert!((bdpt_radiance.g() - path_radiance.g()).abs() < 1e-9, "BDPT {} against path tracer {}", bdpt_radiance.g(), path_radiance.g());
+            }
+            bdpt_sum += bdpt_radiance;
+            path_sum += path_radiance;
+        }
+        for splat in bdpt_splats.pixels() {
+            bdpt_sum += *splat;
+        }
+        let (bdpt_mean, path_mean) = (bdpt_sum.g() / samples as f64, path_sum.g() / samples as f64);
+        assert!((bdpt_mean - path_mean).abs() < 0.02 * path_mean, "BDPT {} against path tracer {}", bdpt_mean, path_mean);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, OnceLock};
+use crate::basic::*;
+use crate::constants::{gamma_to_linear, INFINITY, SURFACE_EPSILON, VOLUME_EPSILON};
+use crate::pdf::{cosine_pdf, Pdf};
+use crate::material::Scatter;
+use crate::film::SampleAovs;
+use crate::hittable::Hit;
+use super::{Integrator, RenderStatistics, Scene, Splats};
+
+/// What a debug view shows of the first thing each camera ray hits.
+#[derive(Copy, Clone, Debug)]
+pub enum DebugView {
+    AmbientOcclusion(f64), // Fraction of the hemisphere unoccluded within the radius
+    ShadingNormals,
+    GeometricNormals,
+    FrontFace, // Green for front faces, red for back faces, blue inside media
+    Albedo,
+    MaterialId, // A color per material, numbered in the order the scene lists them
+    Depth(f64) // Distance along the viewing direction, white at the given distance and beyond
+}
+
+/// Shows one property of the first hit instead of the light arriving there. Rays that miss
+/// everything come out black.
+pub struct DebugIntegrator {
+    view: DebugView,
+    material_ids: OnceLock<HashMap<usize, u64>> // Material address to number, for MaterialId
+}
+
+pub fn debug_integrator(view: DebugView) -> DebugIntegrator { DebugIntegrator { view, material_ids: OnceLock::new() } }
+
+/// Ambient occlusion with rays reaching as far as radius.
+pub fn ambient_occlusion(radius: f64) -> DebugIntegrator { debug_integrator(DebugView::AmbientOcclusion(radius)) }
+
+/// Map a unit vector's components from [-1, 1] to [0, 1], stored so they survive gamma encoding.
+fn direction_color(v: &Vec) -> Color {
+    let channel = |x: f64| gamma_to_linear(0.5 * (x + 1.0));
+    color(channel(v.x()), channel(v.y()), channel(v.z()))
+}
+
+fn material_key(mat: &Arc<dyn Scatter + Sync + Send>) -> usize { Arc::as_ptr(mat) as *const () as usize }
+
+impl DebugIntegrator {
+    /// Number of mat, from the order the world lists its materials rather than their addresses,
+    /// which change from run to run. Materials the world does not list get None.
+    fn material_id(&self, mat: &Arc<dyn Scatter + Sync + Send>, world: &dyn Hit) -> Option<u64> {
+        let ids = self.material_ids.get_or_init(|| {
+            let mut ids = HashMap::new();
+            world.for_each_material(&mut |mat| {
+                let next = ids.len() as u64;
+                ids.entry(material_key(mat)).or_insert(next);
+            });
+            ids
+        });
+        ids.get(&material_key(mat)).copied()
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn li(&self, r: &Ray, scene: &Scene, stats: &mut RenderStatistics, _splats: &mut Splats, _aovs: &mut SampleAovs) -> Color {
+        stats.camera_rays += 1;
+        stats.segments += 1;
+        let Some(rec) = scene.world.hit(r, interval(SURFACE_EPSILON, INFINITY)) else {
+            return black();
+        };
+        match self.view {
+            DebugView::AmbientOcclusion(radius) => {
+                // Cosine weighted around the normal, or over the whole sphere inside a medium.
+                let direction = if rec.volume { rand_unit_vec() } else { cosine_pdf(&rec.normal).generate().unit() };
+                let t_min = if rec.volume { VOLUME_EPSILON } else { SURFACE_EPSILON };
+                if radius <= t_min {
+                    return white();
+                }
+                stats.segments += 1;
+                scene.world.transmittance(&ray(rec.p, direction, r.time()), interval(t_min, radius)) * white()
+            }
+            DebugView::ShadingNormals if !rec.volume => direction_color(&rec.normal),
+            DebugView::GeometricNormals if !rec.volume => direction_color(&rec.geometric_normal),
+            DebugView::ShadingNormals | DebugView::GeometricNormals => black(),
+            DebugView::FrontFace => {
+                if rec.volume { color(0.0, 0.0, 1.0) } else if rec.front_face { color(0.0, 1.0, 0.0) } else { color(1.0, 0.0, 0.0) }
+            }
+            DebugView::Albedo => rec.mat.albedo(&rec),
+            DebugView::MaterialId => {
+                let mut hasher = DefaultHasher::new();
+                self.material_id(&rec.mat, scene.world).hash(&mut hasher);
+                let bits = hasher.finish();
+                let channel = |shift: u32| gamma_to_linear(0.2 + 0.8 * ((bits >> shift) & 0xff) as f64 / 255.0);
+                color(channel(0), channel(8), channel(16))
+            }
+            DebugView::Depth(far) => {
+                let depth = scene.camera.depth(&rec.p, r.time());
+                let value = if far > 0.0 { (depth / far).clamp(0.0, 1.0) } else { 0.0 };
+                gamma_to_linear(value) * white()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::solid_background;
+    use crate::camera::camera;
+    use crate::film::sample_aovs;
+    use crate::hittable::{hittable_list, sphere, HittableList};
+    use crate::integrator::splats;
+    use crate::material::lambertian::lambertian;
+
+    // Five spheres in a row along x, each with its own material, rebuilt so addresses differ.
+    fn row_of_spheres() -> HittableList {
+        let mut world = hittable_list(Arc::new(sphere(point(-4.0, 0.0, -5.0), 0.9, Arc::new(lambertian(color(0.5, 0.5, 0.5))))));
+        for i in 1..5 {
+            world.add(Arc::new(sphere(point(-4.0 + 2.0 * i as f64, 0.0, -5.0), 0.9, Arc::new(lambertian(color(0.5, 0.5, 0.5))))));
+        }
+        world
+    }
+
+    #[test]
+    fn material_ids_do_not_depend_on_hit_order() {
+        let cam = camera();
+        let background = solid_background(black());
+        let mut stats = RenderStatistics::default();
+        let mut splats = splats(1, 1);
+        let mut aovs = sample_aovs(0);
+        let rays: std::vec::Vec<Ray> = (0..5).map(|i| ray(center_point(), vec(-4.0 + 2.0 * i as f64, 0.0, -5.0), 0.0)).collect();
+
+        // The second render sees the spheres in the opposite order.
+        let mut renders = std::vec::Vec::new();
+        for order in [[0, 1, 2, 3, 4], [4, 3, 2, 1, 0]] {
+            let world = row_of_spheres();
+            let scene = Scene { camera: &cam, world: &world, lights: None, background: &background, background_sampled: false, light_groups: &[] };
+            let integrator = debug_integrator(DebugView::MaterialId);
+            let mut colors = [black(); 5];
+            for i in order {
+                colors[i] = integrator.li(&rays[i], &scene, &mut stats, &mut splats, &mut aovs);
+            }
+            let ids: std::vec::Vec<Option<u64>> = rays.iter().map(|r| integrator.material_id(&world.hit(r, interval(SURFACE_EPSILON, INFINITY)).unwrap().mat, &world)).collect();
+            assert_eq!(ids, (0..5).map(Some).collect::<std::vec::Vec<_>>());
+            renders.push(colors);
+        }
+        for (i, (a, b)) in renders[0].iter().zip(&renders[1]).enumerate() {
+            assert_eq!((a.r(), a.g(), a.b()), (b.r(), b.g(), b.b()), "Sphere {} changed color between renders", i);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::basic::*;
+use crate::camera::get_ray;
+use crate::constants::*;
+use crate::film::{sample_aovs, SampleAovs};
+use super::{Integrator, PathTracer, path_tracer, RenderStatistics, Scene, Splats, splats};
+
+/// Primary sample space Metropolis light transport after Kelemen et al. A path is identified with
+/// the random numbers the path tracer draws for it, the first two choosing the pixel. Markov
+/// chains mutate these numbers, slightly in small steps or all afresh in large steps, and visit
+/// paths in proportion to their luminance, so the few paths that carry light in difficult scenes
+/// get most of the work. A bootstrap pass of independent paths measures the average luminance
+/// that scales the result back to absolute brightness.
+pub struct Metropolis {
+    path_tracer: PathTracer,
+    bootstrap_samples: usize,
+    chains: usize,
+    sigma: f64,
+    large_step_probability: f64,
+    seed: Option<u64> // Drawn afresh for every render if None
+}
+
+pub fn metropolis() -> Metropolis {
+    Metropolis {
+        path_tracer: path_tracer(),
+        bootstrap_samples: MLT_BOOTSTRAP_SAMPLES,
+        chains: MLT_CHAINS,
+        sigma: MLT_SIGMA,
+        large_step_probability: MLT_LARGE_STEP_PROBABILITY,
+        seed: None
+    }
+}
+
+/// The same seed renders the same scene to the same image, however the threads are scheduled.
+pub fn metropolis_seeded(seed: u64) -> Metropolis { Metropolis { seed: Some(seed), ..metropolis() } }
+
+/// One coordinate of the sample vector, with what is needed to undo a rejected mutation.
+#[derive(Copy, Clone)]
+struct PrimarySample {
+    value: f64,
+    backup: f64,
+    last_modified: u64, // Iteration that last brought the value up to date
+    last_modified_backup: u64
+}
+
+/// The sample vector of a Markov chain. Coordinates are mutated lazily when the path tracer asks
+/// for them, catching up on all the small steps they missed since they were last used.
+struct MltSampler {
+    rng: StdRng,
+    sigma: f64,
+    large_step_probability: f64,
+    samples: std::vec::Vec<PrimarySample>,
+    accepted_len: usize, // Coordinates in use when the iteration started
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64
+}
+
+fn mlt_sampler(seed: u64, sigma: f64, large_step_probability: f64) -> MltSampler {
+    MltSampler {
+        rng: StdRng::seed_from_u64(seed),
+        sigma,
+        large_step_probability,
+        samples: std::vec::Vec::new(),
+        accepted_len: 0,
+        index: 0,
+        iteration: 0,
+        large_step: true,
+        last_large_step: 0
+    }
+}
+
+impl MltSampler {
+    /// Propose a mutation, applied as the path tracer reads the coordinates.
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+        self.accepted_len = self.samples.len();
+        self.index = 0;
+    }
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+    fn reject(&mut self) {
+        // Coordinates first drawn by the rejected path must not keep values that helped reject it.
+        self.samples.truncate(self.accepted_len);
+        for sample in self.samples.iter_mut().filter(|sample| sample.last_modified == self.iteration) {
+            sample.value = sample.backup;
+            sample.last_modified = sample.last_modified_backup;
+        }
+        self.iteration -= 1;
+    }
+    fn ensure_ready(&mut self, index: usize) {
+        // Coordinates the chain has not used yet are uniformly distributed, draw them afresh.
+        while index >= self.samples.len() {
+            let value = self.rng.gen();
+            self.samples.push(PrimarySample { value, backup: value, last_modified: self.iteration, last_modified_backup: self.iteration });
+        }
+        let sample = &mut self.samples[index];
+        // A coordinate unused since the last accepted large step takes that step's fresh value first.
+        if sample.last_modified < self.last_large_step {
+            sample.value = self.rng.gen();
+            sample.last_modified = self.last_large_step;
+        }
+        sample.backup = sample.value;
+        sample.last_modified_backup = sample.last_modified;
+        if self.large_step {
+            sample.value = self.rng.gen();
+        } else {
+            // The missed small steps add up to one Gaussian step with their combined variance.
+            let steps = (self.iteration - sample.last_modified) as f64;
+            let normal = (-2.0 * (1.0 - self.rng.gen::<f64>()).ln()).sqrt() * (2.0 * PI * self.rng.gen::<f64>()).cos();
+            sample.value += normal * self.sigma * steps.sqrt();
+            sample.value = (sample.value - sample.value.floor()).min(1.0 - f64::EPSILON);
+        }
+        sample.last_modified = self.iteration;
+    }
+}
+
+impl RandomSource for MltSampler {
+    fn next(&mut self) -> f64 {
+        let index = self.index;
+        self.ensure_ready(index);
+        self.index += 1;
+        self.samples[index].value
+    }
+}
+
+/// Proposed and accepted mutations of each kind.
+#[derive(Copy, Clone, Default)]
+struct MutationStatistics {
+    small_proposed: u64,
+    small_accepted: u64,
+    large_proposed: u64,
+    large_accepted: u64
+}
+
+impl MutationStatistics {
+    fn record(&mut self, large_step: bool, accepted: bool) {
+        if large_step {
+            self.large_proposed += 1;
+            self.large_accepted += accepted as u64;
+        } else {
+            self.small_proposed += 1;
+            self.small_accepted += accepted as u64;
+        }
+    }
+    fn merge(&mut self, other: &MutationStatistics) {
+        self.small_proposed += other.small_proposed;
+        self.small_accepted += other.small_accepted;
+        self.large_proposed += other.large_proposed;
+        self.large_accepted += other.large_accepted;
+    }
+    fn print(&self) {
+        let rate = |accepted: u64, proposed: u64| if proposed == 0 { 0.0 } else { 100.0 * accepted as f64 / proposed as f64 };
+        println!("Small steps: {} proposed, {:.1}% accepted.", self.small_proposed, rate(self.small_accepted, self.small_proposed));
+        println!("Large steps: {} proposed, {:.1}% accepted.", self.large_proposed, rate(self.large_accepted, self.large_proposed));
+    }
+}
+
+/// The scalar a chain's visits are proportional to.
+fn contribution(radiance: &Color) -> f64 {
+    let y = radiance.luminance();
+    if y.is_finite() && y > 0.0 { y } else { 0.0 }
+}
+
+impl Metropolis {
+    /// Trace the path given by the sampler's current sample vector, returning its pixel and radiance.
+    fn evaluate(&self, scene: &Scene, sampler: Box<MltSampler>, splats: &mut Splats, stats: &mut RenderStatistics)
+                -> ((i32, i32), Color, Box<MltSampler>) {
+        let (width, height) = scene.camera.image_size();
+        let mut aovs = sample_aovs(0);
+        let ((pixel, radiance), sampler) = with_random_source(sampler, || {
+            let i = ((random_double() * width as f64) as i32).min(width - 1);
+            let j = ((random_double() * height as f64) as i32).min(height - 1);
+            ((i, j), self.path_tracer.li(&get_ray(scene.camera, i, j), scene, stats, splats, &mut aovs))
+        });
+        (pixel, radiance, sampler)
+    }
+}
+
+impl Integrator for Metropolis {
+    /// Pixel by pixel there are no chains to run, this is the plain path tracer.
+    fn li(&self, r: &Ray, scene: &Scene, stats: &mut RenderStatistics, splats: &mut Splats, aovs: &mut SampleAovs) -> Color {
+        self.path_tracer.li(r, scene, stats, splats, aovs)
+    }
+    fn render_image(&self, scene: &Scene, samples_per_pixel: i32, stats: &mut RenderStatistics) -> Option<std::vec::Vec<Color>> {
+        let (width, height) = scene.camera.image_size();
+        let count = (width * height) as usize;
+        let threads = THREADS_NUM.max(1) as usize;
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        // Consecutive seeds from a scrambled base, so nearby seeds do not share most of their paths.
+        let base = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let sampler_seed = |index: usize| base.wrapping_add(index as u64);
+
+        // Bootstrap: independent paths, each reproducible from its index.
+        let bootstrap = self.bootstrap_samples.max(1);
+        let mut weights = vec![0.0; bootstrap];
+        println!("Bootstrapping with {} paths.", bootstrap);
+        thread::scope(|s| {
+            let handles: std::vec::Vec<_> = weights.chunks_mut(bootstrap.div_ceil(threads)).enumerate().map(|(k, weights)| {
+                let offset = k * bootstrap.div_ceil(threads);
+                s.spawn(move || {
+                    // The path tracer never splats, this only satisfies li's signature.
+                    let mut splats = splats(0, 0);
+                    let mut stats = RenderStatistics::default();
+                    for (i, weight) in weights.iter_mut().enumerate() {
+                        let sampler = Box::new(mlt_sampler(sampler_seed(offset + i), self.sigma, self.large_step_probability));
+                        *weight = contribution(&self.evaluate(scene, sampler, &mut splats, &mut stats).1);
+                    }
+                    stats.collect_traversal();
+                    stats
+                })
+            }).collect();
+            for handle in handles {
+                stats.merge(&handle.join().expect("Error occurred when joining threads"));
+            }
+        });
+        let distribution = distribution_1d(weights);
+        let brightness = distribution.integral();
+        if brightness <= 0.0 {
+            return Some(vec![black(); count]);
+        }
+
+        // Markov chains, each starting from a bootstrap path picked by its contribution.
+        let chains = self.chains.max(1);
+        let mutations = samples_per_pixel.max(1) as usize * count;
+        let done = AtomicUsize::new(0);
+        let mut film = vec![black(); count];
+        let mut mutation_statistics = MutationStatistics::default();
+        thread::scope(|s| {
+            let handles: std::vec::Vec<_> = (0..threads).map(|k| {
+                let (distribution, done) = (&distribution, &done);
+                s.spawn(move || {
+                    let mut splats = splats(0, 0);
+                    let mut stats = RenderStatistics::default();
+                    let mut mutation_statistics = MutationStatistics::default();
+                    let mut film = vec![black(); count];
+                    for chain in (k..chains).step_by(threads) {
+                        let mut rng = StdRng::seed_from_u64(sampler_seed(bootstrap + chain));
+                        let start = distribution.sample_continuous(rng.gen()).2;
+                        let sampler = Box::new(mlt_sampler(sampler_seed(start), self.sigma, self.large_step_probability));
+                        let (mut pixel, mut radiance, mut sampler) = self.evaluate(scene, sampler, &mut splats, &mut stats);
+                        let steps = mutations / chains + usize::from(chain < mutations % chains);
+                        for _ in 0..steps {
+                            sampler.start_iteration();
+                            let (proposed_pixel, proposed, proposed_sampler) = self.evaluate(scene, sampler, &mut splats, &mut stats);
+                            sampler = proposed_sampler;
+                            let (current_y, proposed_y) = (contribution(&radiance), contribution(&proposed));
+                            let accept = if current_y > 0.0 { (proposed_y / current_y).min(1.0) } else { 1.0 };
+                            // Both states are recorded, weighted by their chance of being the next one.
+                            if accept > 0.0 {
+                                film[(proposed_pixel.1 * width + proposed_pixel.0) as usize] += accept / proposed_y * proposed;
+                            }
+                            if current_y > 0.0 {
+                                film[(pixel.1 * width + pixel.0) as usize] += (1.0 - accept) / current_y * radiance;
+                            }
+                            let accepted = rng.gen::<f64>() < accept;
+                            mutation_statistics.record(sampler.large_step, accepted);
+                            if accepted {
+                                pixel = proposed_pixel;
+                                radiance = proposed;
+                                sampler.accept();
+                            } else {
+                                sampler.reject();
+                            }
+                        }
+                        print!("\rChains done: {}/{}.", done.fetch_add(1, Ordering::Relaxed) + 1, chains);
+                        io::stdout().flush().expect("IO message error!");
+                    }
+                    stats.collect_traversal();
+                    (film, stats, mutation_statistics)
+                })
+            }).collect();
+            // Summed in thread order so a seeded render is reproducible to the last bit.
+            for handle in handles {
+                let (thread_film, thread_stats, thread_mutations) = handle.join().expect("Error occurred when joining threads");
+                for (pixel, value) in film.iter_mut().zip(thread_film) {
+                    *pixel += value;
+                }
+                stats.merge(&thread_stats);
+                mutation_statistics.merge(&thread_mutations);
+            }
+        });
+        println!();
+        println!("Average path luminance: {:.4}", brightness);
+        mutation_statistics.print();
+        // Each pixel received samples_per_pixel mutations on average, worth brightness each.
+        Some(film.into_iter().map(|pixel| brightness * pixel).collect())
+    }
+}
@@ -0,0 +1,88 @@
+use crate::basic::*;
+use crate::constants::{INFINITY, MAX_DEPTH, MAX_SURVIVAL, MIN_DEPTH, random_double, SURFACE_EPSILON, VOLUME_EPSILON};
+use crate::film::SampleAovs;
+use super::{Integrator, RenderStatistics, Scene, Splats};
+
+/// A unidirectional path tracer. Every diffuse vertex samples one light direction and combines
+/// it with the material's own sample by multiple importance sampling. Past min_depth bounces
+/// each path survives with a probability following its throughput and is reweighted to stay
+/// unbiased, max_depth optionally cuts it off regardless.
+pub struct PathTracer {
+    min_depth: i32,
+    max_depth: Option<i32>
+}
+
+pub fn path_tracer() -> PathTracer { PathTracer { min_depth: MIN_DEPTH, max_depth: MAX_DEPTH } }
+
+/// A cap trades a little energy in deep paths for a bounded cost.
+pub fn path_tracer_depth(min_depth: i32, max_depth: Option<i32>) -> PathTracer { PathTracer { min_depth, max_depth } }
+
+impl Integrator for PathTracer {
+    fn li(&self, r: &Ray, scene: &Scene, stats: &mut RenderStatistics, _splats: &mut Splats, aovs: &mut SampleAovs) -> Color {
+        let mut radiance = black();
+        let mut throughput = white();
+        let mut current = *r;
+        // t_min skips the surface the ray starts on, rays leaving a scattering event inside a
+        // medium start in free space and use a much smaller offset.
+        let mut t_min = SURFACE_EPSILON;
+        // Densities with which the previous vertex's material and light sampling would have
+        // chosen the current ray. The first is zero for camera rays and specular bounces, whose
+        // light is not sampled explicitly.
+        let mut mis = (0.0, 0.0);
+        let mut depth = 0;
+        stats.camera_rays += 1;
+        loop {
+            if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                break;
+            }
+            stats.segments += 1;
+            let Some(hit_record) = scene.world.hit(&current, interval(t_min, INFINITY)) else {
+                let light = throughput * scene.weighted_emission(&current, None, mis);
+                aovs.add_light(light, depth, None);
+                radiance += light;
+                break;
+            };
+            let mat = &*hit_record.mat;
+            if depth == 0 {
+                aovs.albedo = mat.albedo(&hit_record);
+                aovs.normal = if hit_record.volume { empty_vec() } else { hit_record.normal };
+                aovs.depth = scene.camera.depth(&hit_record.p, current.time());
+            }
+            let light = throughput * scene.weighted_emission(&current, Some(&hit_record), mis);
+            aovs.add_light(light, depth, scene.light_group(&hit_record.mat));
+            radiance += light;
+            let Some(scatter_record) = mat.scatter(&current, &hit_record) else {
+                break;
+            };
+            let scattered = scatter_record.scattered;
+            let next_t_min = if hit_record.volume { VOLUME_EPSILON } else { SURFACE_EPSILON };
+            let (_, bsdf_pdf) = mat.eval(&current, &hit_record, &scattered);
+
+            // One light sample, weighted against the chance of the material sampling the same direction.
+            let mut light_pdf = 0.0;
+            if bsdf_pdf > 0.0 {
+                let (direct, group) = scene.sample_direct(&current, &hit_record, next_t_min);
+                aovs.add_light(throughput * direct, depth + 1, group);
+                radiance += throughput * direct;
+                light_pdf = scene.with_light_pdf(&hit_record.p, scattered.time(), |pdf| pdf.value(scattered.direction())).unwrap_or(0.0);
+            }
+
+            throughput *= scatter_record.attenuation;
+            if depth + 1 >= self.min_depth {
+                let survival = throughput.r().max(throughput.g()).max(throughput.b()).min(MAX_SURVIVAL);
+                if random_double() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+            mis = (bsdf_pdf.max(0.0), light_pdf);
+            current = scattered;
+            t_min = next_t_min;
+            depth += 1;
+        }
+        radiance
+    }
+    fn supports_aovs(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::thread;
+use crate::basic::*;
+use crate::camera::get_ray;
+use crate::constants::*;
+use crate::hittable::HitRecord;
+use crate::material::ScatterRecord;
+use crate::pdf::{background_pdf, local_to_world, Pdf};
+use crate::film::SampleAovs;
+use super::{Integrator, RenderStatistics, Scene, Splats};
+
+/// Stochastic progressive photon mapping after Hachisuka and Jensen. Every iteration follows one
+/// camera path per pixel through specular bounces and media to its first diffuse surface, then
+/// shoots photons from the lights and the background and gathers those landing within a radius
+/// of that point. The radius shrinks from one iteration to the next so the image converges
+/// without bias, while caustics seen directly or through glass show up from the first iterations.
+/// Direct light at the gather points is sampled explicitly, photons only carry indirect light.
+/// Emitters that are not among the lights, emitting media included, only light the scene directly.
+pub struct ProgressivePhotonMapper {
+    max_depth: i32,
+    photons_per_iteration: Option<usize> // One per pixel if None
+}
+
+pub fn progressive_photon_mapper() -> ProgressivePhotonMapper {
+    ProgressivePhotonMapper { max_depth: SPPM_MAX_DEPTH, photons_per_iteration: None }
+}
+
+pub fn progressive_photon_mapper_photons(photons_per_iteration: usize) -> ProgressivePhotonMapper {
+    ProgressivePhotonMapper { max_depth: SPPM_MAX_DEPTH, photons_per_iteration: Some(photons_per_iteration) }
+}
+
+/// Where a pixel's camera path of the current iteration met its first diffuse surface.
+struct GatherPoint {
+    rec: HitRecord,
+    r_in: Ray,
+    beta: Color, // Throughput of the camera path up to the surface
+    distance: f64 // Travelled by the camera path
+}
+
+/// What a pixel keeps from one iteration to the next.
+#[derive(Copy, Clone)]
+struct PixelState {
+    radius: f64, // Zero until the pixel first finds a gather point
+    photons: f64, // Photons gathered so far, reduced as the radius shrinks
+    flux: Color, // Photon flux within the current radius, scaled with the radius
+    direct: Color // Sum of the camera path estimates of all iterations
+}
+
+/// Photon flux and photon count gathered by each pixel during one iteration.
+struct PhotonTally {
+    flux: std::vec::Vec<Color>,
+    counts: std::vec::Vec<u64>
+}
+
+fn photon_tally(count: usize) -> PhotonTally { PhotonTally { flux: vec![black(); count], counts: vec![0; count] } }
+
+impl PhotonTally {
+    fn merge(&mut self, other: &PhotonTally) {
+        for i in 0..self.flux.len() {
+            self.flux[i] += other.flux[i];
+            self.counts[i] += other.counts[i];
+        }
+    }
+}
+
+/// Gather points hashed into cubic cells at least as wide as the largest radius, so a photon only
+/// checks the points registered in its own cell.
+struct GatherGrid<'a> {
+    points: &'a [Option<GatherPoint>],
+    pixels: &'a [PixelState],
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), std::vec::Vec<usize>>
+}
+
+fn gather_grid<'a>(points: &'a [Option<GatherPoint>], pixels: &'a [PixelState]) -> GatherGrid<'a> {
+    let cell_size = pixels.iter().map(|pixel| pixel.radius).fold(0.0, f64::max);
+    let mut grid = GatherGrid { points, pixels, cell_size, cells: HashMap::new() };
+    if cell_size <= 0.0 {
+        return grid;
+    }
+    for (index, point) in points.iter().enumerate() {
+        let Some(point) = point else { continue; };
+        let radius = vec(pixels[index].radius, pixels[index].radius, pixels[index].radius);
+        let low = grid.cell(&(point.rec.p - radius));
+        let high = grid.cell(&(point.rec.p + radius));
+        for x in low.0..=high.0 {
+            for y in low.1..=high.1 {
+                for z in low.2..=high.2 {
+                    grid.cells.entry((x, y, z)).or_default().push(index);
+                }
+            }
+        }
+    }
+    grid
+}
+
+impl GatherGrid<'_> {
+    fn cell(&self, p: &Point) -> (i64, i64, i64) {
+        let key = |x: f64| (x / self.cell_size).floor() as i64;
+        (key(p.x()), key(p.y()), key(p.z()))
+    }
+    /// Credit a photon with power beta that arrived at p along r to every gather point within its
+    /// pixel's radius of p.
+    fn deposit(&self, p: &Point, r: &Ray, beta: Color, tally: &mut PhotonTally) {
+        if self.cell_size <= 0.0 {
+            return;
+        }
+        let Some(indices) = self.cells.get(&self.cell(p)) else { return; };
+        let wi = -r.direction().unit();
+        for &index in indices {
+            let Some(point) = &self.points[index] else { continue; };
+            let radius = self.pixels[index].radius;
+            if (point.rec.p - *p).length_squared() > radius * radius {
+                continue;
+            }
+            let cos_theta = dot(&point.rec.normal, &wi).abs();
+            if cos_theta <= 0.0 {
+                continue;
+            }
+            let arriving = ray(point.rec.p, wi, r.time());
+            tally.flux[index] += beta * (*point.rec.mat).eval(&point.r_in, &point.rec, &arriving).0 / cos_theta;
+            tally.counts[index] += 1;
+        }
+    }
+}
+
+/// Light arriving directly at a diffuse surface, one light sample and one material sample
+/// combined by multiple importance sampling.
+fn direct_lighting(scene: &Scene, r_in: &Ray, rec: &HitRecord, scatter_record: &ScatterRecord, bsdf_pdf: f64,
+                   stats: &mut RenderStatistics) -> Color {
+    let scattered = &scatter_record.scattered;
+    let light_pdf = scene.with_light_pdf(&rec.p, scattered.time(), |pdf| pdf.value(scattered.direction())).unwrap_or(0.0);
+    stats.segments += 1;
+    let hit = scene.world.hit(scattered, interval(SURFACE_EPSILON, INFINITY));
+    let incoming = scene.weighted_emission(scattered, hit.as_ref(), (bsdf_pdf, light_pdf));
+    scene.sample_direct(r_in, rec, SURFACE_EPSILON).0 + scatter_record.attenuation * incoming
+}
+
+/// Start a photon at time on one of the lights or, if it gives off any light, the background.
+/// Returns its ray and the power it carries.
+fn emit_photon(scene: &Scene, background_emits: bool, time: f64) -> Option<(Ray, Color)> {
+    let choice_pdf = if scene.lights.is_some() && background_emits { 0.5 } else { 1.0 };
+    let from_lights = match scene.lights {
+        Some(_) => !background_emits || random_double() < 0.5,
+        None if background_emits => false,
+        None => return None
+    };
+    if from_lights {
+        let emission = scene.sample_emission(time)?;
+        return Some((emission.ray, emission.beta() / choice_pdf));
+    }
+    // Parallel light from the sampled direction, entering through a disk that covers the scene.
+    let bbox = scene.world.bounding_box();
+    let center = bbox.centroid();
+    let radius = 0.5 * (bbox.x.size().powi(2) + bbox.y.size().powi(2) + bbox.z.size().powi(2)).sqrt();
+    let sampling = background_pdf(scene.background);
+    let towards = sampling.generate().unit();
+    let pdf_dir = if scene.background_sampled { sampling.value(&towards) } else { 1.0 / (4.0 * PI) };
+    if pdf_dir <= 0.0 || !radius.is_finite() {
+        return None;
+    }
+    let disk = rand_in_unit_disk();
+    let origin = center + radius * (towards + local_to_world(&towards, disk.x(), disk.y(), 0.0));
+    let le = scene.background.value(&ray(center, towards, time));
+    Some((ray(origin, -towards, time), PI * radius * radius / (pdf_dir * choice_pdf) * le))
+}
+
+impl ProgressivePhotonMapper {
+    /// Follow a camera ray through specular bounces and media to its first diffuse surface.
+    /// Returns the light found on the way, including direct light at that surface, and the
+    /// surface as a gather point.
+    fn trace_camera(&self, r: &Ray, scene: &Scene, stats: &mut RenderStatistics) -> (Color, Option<GatherPoint>) {
+        let mut radiance = black();
+        let mut beta = white();
+        let mut current = *r;
+        let mut t_min = SURFACE_EPSILON;
+        let mut distance = 0.0;
+        stats.camera_rays += 1;
+        for _ in 0..self.max_depth {
+            stats.segments += 1;
+            let Some(rec) = scene.world.hit(&current, interval(t_min, INFINITY)) else {
+                radiance += beta * scene.background.value(&current);
+                break;
+            };
+            distance += rec.t * current.direction().length();
+            let mat = &*rec.mat;
+            radiance += beta * rec.emitted();
+            let Some(scatter_record) = mat.scatter(&current, &rec) else {
+                break;
+            };
+            let pdf = mat.eval(&current, &rec, &scatter_record.scattered).1;
+            if pdf > 0.0 && !rec.volume {
+                radiance += beta * direct_lighting(scene, &current, &rec, &scatter_record, pdf, stats);
+                return (radiance, Some(GatherPoint { rec, r_in: current, beta, distance }));
+            }
+            beta *= scatter_record.attenuation;
+            t_min = if rec.volume { VOLUME_EPSILON } else { SURFACE_EPSILON };
+            current = scatter_record.scattered;
+        }
+        (radiance, None)
+    }
+    /// Trace one photon, adding its contribution to the flux and photon count of every gather
+    /// point it lands close enough to after its first bounce.
+    fn trace_photon(&self, scene: &Scene, r: Ray, power: Color, grid: &GatherGrid, tally: &mut PhotonTally, stats: &mut RenderStatistics) {
+        let mut current = r;
+        let mut beta = power;
+        let mut t_min = SURFACE_EPSILON;
+        for depth in 0..self.max_depth {
+            stats.segments += 1;
+            let Some(rec) = scene.world.hit(&current, interval(t_min, INFINITY)) else { return; };
+            let mat = &*rec.mat;
+            let Some(scatter_record) = mat.scatter(&current, &rec) else { return; };
+            let diffuse = mat.eval(&current, &rec, &scatter_record.scattered).1 > 0.0;
+            if depth > 0 && diffuse && !rec.volume {
+                grid.deposit(&rec.p, &current, beta, tally);
+            }
+            beta *= scatter_record.attenuation;
+            if depth + 1 >= MIN_DEPTH {
+                let a = scatter_record.attenuation;
+                let survival = a.r().max(a.g()).max(a.b()).min(MAX_SURVIVAL);
+                if random_double() >= survival {
+                    return;
+                }
+                beta /= survival;
+            }
+            t_min = if rec.volume { VOLUME_EPSILON } else { SURFACE_EPSILON };
+            current = scatter_record.scattered;
+        }
+    }
+}
+
+impl Integrator for ProgressivePhotonMapper {
+    /// Without photons only the light found along the camera path is estimated: direct light at
+    /// the first diffuse surface and emitters seen through specular bounces.
+    fn li(&self, r: &Ray, scene: &Scene, stats: &mut RenderStatistics, _splats: &mut Splats, _aovs: &mut SampleAovs) -> Color {
+        self.trace_camera(r, scene, stats).0
+    }
+    fn render_image(&self, scene: &Scene, samples_per_pixel: i32, stats: &mut RenderStatistics) -> Option<std::vec::Vec<Color>> {
+        let (width, height) = scene.camera.image_size();
+        let count = (width * height) as usize;
+        let threads = THREADS_NUM.max(1) as usize;
+        let chunk = count.div_ceil(threads);
+        let photons = self.photons_per_iteration.unwrap_or(count);
+        let iterations = samples_per_pixel.max(1);
+        // Only backgrounds that give off some light emit photons, black ones would waste them.
+        let center = scene.world.bounding_box().centroid();
+        let background_emits = (0..64).any(|_| scene.background.value(&ray(center, rand_unit_vec(), 0.0)).luminance() > 0.0);
+
+        let mut pixels = vec![PixelState { radius: 0.0, photons: 0.0, flux: black(), direct: black() }; count];
+        let mut points: std::vec::Vec<Option<GatherPoint>> = (0..count).map(|_| None).collect();
+        let mut direct = vec![black(); count];
+        for iteration in 0..iterations {
+            print!("\rIteration {}/{}.", iteration + 1, iterations);
+            io::stdout().flush().expect("IO message error!");
+
+            // Camera pass: one gather point per pixel.
+            thread::scope(|s| {
+                let handles: std::vec::Vec<_> = points.chunks_mut(chunk).zip(direct.chunks_mut(chunk)).enumerate()
+                    .map(|(k, (points, direct))| s.spawn(move || {
+                        let mut stats = RenderStatistics::default();
+                        for (offset, (point, light)) in points.iter_mut().zip(direct.iter_mut()).enumerate() {
+                            let index = (k * chunk + offset) as i32;
+                            let r = get_ray(scene.camera, index % width, index / width);
+                            (*light, *point) = self.trace_camera(&r, scene, &mut stats);
+                        }
+                        stats.collect_traversal();
+                        stats
+                    })).collect();
+                for handle in handles {
+                    stats.merge(&handle.join().expect("Error occurred when joining threads"));
+                }
+            });
+            for (pixel, point) in pixels.iter_mut().zip(&points) {
+                if let Some(point) = point {
+                    if pixel.radius <= 0.0 {
+                        pixel.radius = SPPM_INITIAL_RADIUS * scene.camera.pixel_spread() * point.distance;
+                    }
+                }
+            }
+
+            // Photon pass, each thread gathering into its own buffers.
+            let grid = gather_grid(&points, &pixels);
+            let mut tally = photon_tally(count);
+            thread::scope(|s| {
+                let handles: std::vec::Vec<_> = (0..threads).map(|k| {
+                    let grid = &grid;
+                    s.spawn(move || {
+                        let mut stats = RenderStatistics::default();
+                        let mut tally = photon_tally(count);
+                        for _ in (k..photons).step_by(threads) {
+                            let Some((r, power)) = emit_photon(scene, background_emits, scene.camera.random_time()) else { continue; };
+                            self.trace_photon(scene, r, power, grid, &mut tally, &mut stats);
+                        }
+                        stats.collect_traversal();
+                        (tally, stats)
+                    })
+                }).collect();
+                for handle in handles {
+                    let (thread_tally, thread_stats) = handle.join().expect("Error occurred when joining threads");
+                    tally.merge(&thread_tally);
+                    stats.merge(&thread_stats);
+                }
+            });
+
+            // Shrink the radius of every pixel that gathered photons, keeping a fraction of them.
+            for i in 0..count {
+                let pixel = &mut pixels[i];
+                pixel.direct += direct[i];
+                let (Some(point), gathered) = (&points[i], tally.counts[i]) else { continue; };
+                if gathered == 0 {
+                    continue;
+                }
+                let photons = pixel.photons + SPPM_ALPHA * gathered as f64;
+                let radius = pixel.radius * (photons / (pixel.photons + gathered as f64)).sqrt();
+                pixel.flux = (pixel.flux + point.beta * tally.flux[i]) * (radius * radius / (pixel.radius * pixel.radius));
+                pixel.photons = photons;
+                pixel.radius = radius;
+            }
+        }
+        println!();
+
+        let emitted = photons as f64 * iterations as f64;
+        Some(pixels.iter().map(|pixel| {
+            let indirect = if pixel.radius > 0.0 { pixel.flux / (emitted * PI * pixel.radius * pixel.radius) } else { black() };
+            pixel.direct + iterations as f64 * indirect
+        }).collect())
+    }
+}
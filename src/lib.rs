@@ -0,0 +1,11 @@
+pub mod basic;
+pub mod hittable;
+pub mod constants;
+pub mod camera;
+pub mod material;
+pub mod loader;
+pub mod texture;
+pub mod background;
+pub mod pdf;
+pub mod integrator;
+pub mod film;
@@ -0,0 +1,50 @@
+pub mod obj;
+pub mod ply;
+pub mod image;
+pub mod voxel;
+
+use std::fmt::{Display, Formatter};
+
+/// Error raised while importing a scene file. Parse errors carry the file and the 1-based line,
+/// format errors are for data without lines such as binary payloads.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(String, std::io::Error),
+    Parse { file: String, line: usize, message: String },
+    Format { file: String, message: String }
+}
+
+pub fn parse_error(file: &str, line: usize, message: String) -> LoadError {
+    LoadError::Parse { file: file.to_string(), line, message }
+}
+
+pub fn format_error(file: &str, message: String) -> LoadError {
+    LoadError::Format { file: file.to_string(), message }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(file, err) => write!(f, "{}: {}", file, err),
+            LoadError::Parse { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+            LoadError::Format { file, message } => write!(f, "{}: {}", file, message)
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+fn read_file(path: &str) -> Result<String, LoadError> {
+    std::fs::read_to_string(path).map_err(|err| LoadError::Io(path.to_string(), err))
+}
+
+/// Write contents to name in a directory of its own under the system's temporary directory,
+/// returning the path, for loader tests.
+#[cfg(test)]
+fn temp_file(name: &str, contents: &[u8]) -> String {
+    let dir = std::env::temp_dir().join(format!("ray_tracing_loader_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path.display().to_string()
+}
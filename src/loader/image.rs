@@ -0,0 +1,409 @@
+use std::fs::File;
+use std::io::BufReader;
+use crate::basic::{Color, color};
+use crate::constants::{gamma_to_linear, srgb_to_linear};
+use crate::texture::image::{Image, image};
+use super::{format_error, LoadError, parse_error};
+
+/// Load a PPM (P3 or P6), PNG, Radiance HDR or PFM image, chosen by extension, and convert it to
+/// linear RGB. PPM files are decoded with the inverse of `linear_to_gamma`, so renders read back
+/// unchanged, PNG files with the sRGB transfer curve. HDR and PFM files are already linear.
+pub fn load_image(path: &str) -> Result<Image, LoadError> {
+    let lower = path.to_lowercase();
+    if lower.ends_with(".png") {
+        load_png(path, srgb_to_linear)
+    } else if lower.ends_with(".hdr") || lower.ends_with(".pic") {
+        load_hdr(path)
+    } else if lower.ends_with(".pfm") {
+        load_pfm(path)
+    } else {
+        load_ppm(path, gamma_to_linear)
+    }
+}
+
+/// Load an image without any transfer curve, for data such as bump maps.
+pub fn load_image_raw(path: &str) -> Result<Image, LoadError> {
+    if path.to_lowercase().ends_with(".png") {
+        load_png(path, |x| x)
+    } else {
+        load_ppm(path, |x| x)
+    }
+}
+
+/// Read the whitespace separated header fields of a PPM file, skipping comments. Returns the
+/// fields and the offset of the first byte after the last field's terminating whitespace.
+fn ppm_header(file: &str, data: &[u8], fields: usize) -> Result<(std::vec::Vec<String>, usize, usize), LoadError> {
+    let mut ret = std::vec::Vec::new();
+    let mut offset = 0;
+    let mut line = 1;
+    while ret.len() < fields {
+        if offset >= data.len() {
+            return Err(format_error(file, "unexpected end of file in header".to_string()));
+        }
+        let c = data[offset];
+        if c == b'#' {
+            while offset < data.len() && data[offset] != b'\n' { offset += 1; }
+        } else if c.is_ascii_whitespace() {
+            if c == b'\n' { line += 1; }
+            offset += 1;
+        } else {
+            let start = offset;
+            while offset < data.len() && !data[offset].is_ascii_whitespace() { offset += 1; }
+            ret.push(String::from_utf8_lossy(&data[start..offset]).to_string());
+        }
+    }
+    // Exactly one whitespace character separates the header from binary data.
+    Ok((ret, offset + 1, line))
+}
+
+/// Number of samples in a width by height image, an error if it is empty or does not fit in
+/// memory at all.
+fn sample_count(path: &str, width: usize, height: usize, channels: usize) -> Result<usize, LoadError> {
+    if width == 0 || height == 0 {
+        return Err(format_error(path, format!("image size {}x{} has no pixels", width, height)));
+    }
+    width.checked_mul(height).and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(|| format_error(path, format!("image size {}x{} is too large", width, height)))
+}
+
+fn load_ppm(path: &str, decode: fn(f64) -> f64) -> Result<Image, LoadError> {
+    let data = std::fs::read(path).map_err(|err| LoadError::Io(path.to_string(), err))?;
+    let (header, body_offset, line) = ppm_header(path, &data, 4)?;
+    let number = |s: &str| s.parse::<usize>().map_err(|_| parse_error(path, line, format!("'{}' is not a number", s)));
+    let width = number(&header[1])?;
+    let height = number(&header[2])?;
+    let max_value = number(&header[3])?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(parse_error(path, line, format!("invalid maximum value {}", max_value)));
+    }
+    let scale = 1.0 / max_value as f64;
+    let count = sample_count(path, width, height, 3)?;
+    let samples: std::vec::Vec<f64> = match header[0].as_str() {
+        "P3" => {
+            let body = String::from_utf8_lossy(&data[body_offset.min(data.len())..]).to_string();
+            // Not preallocated, the header's size is only checked against the samples found.
+            let mut samples = std::vec::Vec::new();
+            for (index, text) in body.lines().enumerate() {
+                for token in text.split('#').next().unwrap_or("").split_whitespace() {
+                    let value = token.parse::<usize>()
+                        .map_err(|_| parse_error(path, line + 1 + index, format!("'{}' is not a sample", token)))?;
+                    samples.push(value as f64 * scale);
+                }
+            }
+            samples
+        }
+        "P6" => {
+            let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+            let body = &data[body_offset.min(data.len())..];
+            if body.len() / bytes_per_sample < count {
+                return Err(format_error(path, format!("expected {} samples of pixel data, found {} bytes", count, body.len())));
+            }
+            (0..count).map(|i| {
+                let value = if bytes_per_sample == 1 {
+                    body[i] as f64
+                } else {
+                    u16::from_be_bytes([body[2 * i], body[2 * i + 1]]) as f64
+                };
+                value * scale
+            }).collect()
+        }
+        magic => return Err(parse_error(path, 1, format!("unsupported PPM type '{}'", magic)))
+    };
+    if samples.len() < count {
+        return Err(format_error(path, format!("expected {} samples, found {}", count, samples.len())));
+    }
+    let pixels = samples[..count].chunks(3).map(|c| color(decode(c[0]), decode(c[1]), decode(c[2]))).collect();
+    Ok(image(width, height, pixels))
+}
+
+fn load_png(path: &str, decode: fn(f64) -> f64) -> Result<Image, LoadError> {
+    let file = File::open(path).map_err(|err| LoadError::Io(path.to_string(), err))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    // Expand palettes and low bit depths, keep 16-bit samples.
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|err| format_error(path, err.to_string()))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|err| format_error(path, err.to_string()))?;
+    let channels = info.color_type.samples();
+    let wide = info.bit_depth == png::BitDepth::Sixteen;
+    let sample = |i: usize| -> f64 {
+        if wide {
+            u16::from_be_bytes([buffer[2 * i], buffer[2 * i + 1]]) as f64 / 65535.0
+        } else {
+            buffer[i] as f64 / 255.0
+        }
+    };
+    let (width, height) = (info.width as usize, info.height as usize);
+    let mut pixels: std::vec::Vec<Color> = std::vec::Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let base = y * info.line_size / if wide { 2 } else { 1 } + x * channels;
+            let pixel = match channels {
+                1 | 2 => { let l = decode(sample(base)); color(l, l, l) }
+                _ => color(decode(sample(base)), decode(sample(base + 1)), decode(sample(base + 2)))
+            };
+            pixels.push(pixel);
+        }
+    }
+    Ok(image(width, height, pixels))
+}
+
+/// Decode one RGBE pixel: three 8-bit mantissas sharing an 8-bit exponent.
+fn rgbe_to_color(rgbe: &[u8]) -> Color {
+    if rgbe[3] == 0 {
+        return color(0.0, 0.0, 0.0);
+    }
+    let f = 2f64.powi(rgbe[3] as i32 - 136); // 2^(e - 128) / 256
+    color(rgbe[0] as f64 * f, rgbe[1] as f64 * f, rgbe[2] as f64 * f)
+}
+
+/// Load a Radiance RGBE (.hdr) image, either flat or with run-length encoded scanlines.
+pub fn load_hdr(path: &str) -> Result<Image, LoadError> {
+    let data = std::fs::read(path).map_err(|err| LoadError::Io(path.to_string(), err))?;
+    let mut offset = 0;
+    let mut line = 0;
+    let mut next_line = |offset: &mut usize| -> Option<String> {
+        let end = data[*offset..].iter().position(|&c| c == b'\n')?;
+        let text = String::from_utf8_lossy(&data[*offset..*offset + end]).to_string();
+        *offset += end + 1;
+        line += 1;
+        Some(text)
+    };
+    let eof = || format_error(path, "unexpected end of file in header".to_string());
+    let magic = next_line(&mut offset).ok_or_else(eof)?;
+    if !magic.starts_with("#?") {
+        return Err(parse_error(path, 1, "missing '#?RADIANCE' signature".to_string()));
+    }
+    loop {
+        let text = next_line(&mut offset).ok_or_else(eof)?;
+        if text.trim().is_empty() {
+            break;
+        }
+        if let Some(format) = text.strip_prefix("FORMAT=") {
+            if format.trim() != "32-bit_rle_rgbe" {
+                return Err(parse_error(path, line, format!("unsupported format '{}'", format.trim())));
+            }
+        }
+    }
+    let resolution = next_line(&mut offset).ok_or_else(eof)?;
+    let tokens: std::vec::Vec<&str> = resolution.split_whitespace().collect();
+    if tokens.len() != 4 || tokens[0] != "-Y" || tokens[2] != "+X" {
+        return Err(parse_error(path, line, format!("unsupported resolution line '{}'", resolution)));
+    }
+    let number = |s: &str| s.parse::<usize>().map_err(|_| parse_error(path, line, format!("'{}' is not a number", s)));
+    let height = number(tokens[1])?;
+    let width = number(tokens[3])?;
+
+    let truncated = || format_error(path, "pixel data is truncated".to_string());
+    sample_count(path, width, height, 4)?;
+    // Scanlines this wide cannot be run-length encoded, so the file must hold them uncompressed.
+    if width >= 0x8000 && width * 4 > data.len() - offset {
+        return Err(truncated());
+    }
+    let mut pixels: std::vec::Vec<Color> = std::vec::Vec::new();
+    let mut scanline = vec![0u8; width * 4];
+    for _ in 0..height {
+        let header = data.get(offset..offset + 4).ok_or_else(truncated)?;
+        let is_rle = (8..0x8000).contains(&width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0;
+        if !is_rle {
+            let bytes = data.get(offset..offset + width * 4).ok_or_else(truncated)?;
+            scanline.copy_from_slice(bytes);
+            offset += width * 4;
+        } else {
+            if ((header[2] as usize) << 8 | header[3] as usize) != width {
+                return Err(format_error(path, "scanline width does not match the image".to_string()));
+            }
+            offset += 4;
+            // Each of the four channels is run-length encoded separately.
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = *data.get(offset).ok_or_else(truncated)? as usize;
+                    offset += 1;
+                    if count > 128 {
+                        let run = count - 128;
+                        let value = *data.get(offset).ok_or_else(truncated)?;
+                        offset += 1;
+                        if x + run > width {
+                            return Err(format_error(path, "run overflows the scanline".to_string()));
+                        }
+                        for _ in 0..run {
+                            scanline[x * 4 + channel] = value;
+                            x += 1;
+                        }
+                    } else {
+                        if count == 0 || x + count > width {
+                            return Err(format_error(path, "invalid run length".to_string()));
+                        }
+                        let bytes = data.get(offset..offset + count).ok_or_else(truncated)?;
+                        for &value in bytes {
+                            scanline[x * 4 + channel] = value;
+                            x += 1;
+                        }
+                        offset += count;
+                    }
+                }
+            }
+        }
+        pixels.extend(scanline.chunks(4).map(rgbe_to_color));
+    }
+    Ok(image(width, height, pixels))
+}
+
+/// Load a portable float map, color (PF) or grey-scale (Pf). Rows are stored bottom to top and
+/// a negative scale marks little endian data.
+pub fn load_pfm(path: &str) -> Result<Image, LoadError> {
+    let data = std::fs::read(path).map_err(|err| LoadError::Io(path.to_string(), err))?;
+    let (header, body_offset, line) = ppm_header(path, &data, 4)?;
+    let channels = match header[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        magic => return Err(parse_error(path, 1, format!("unsupported PFM type '{}'", magic)))
+    };
+    let width = header[1].parse::<usize>().map_err(|_| parse_error(path, line, format!("'{}' is not a number", header[1])))?;
+    let height = header[2].parse::<usize>().map_err(|_| parse_error(path, line, format!("'{}' is not a number", header[2])))?;
+    let scale = header[3].parse::<f64>().map_err(|_| parse_error(path, line, format!("'{}' is not a number", header[3])))?;
+    let little_endian = scale < 0.0;
+    let count = sample_count(path, width, height, channels)?;
+    let body = &data[body_offset.min(data.len())..];
+    if body.len() / 4 < count {
+        return Err(format_error(path, format!("expected {} samples of pixel data, found {} bytes", count, body.len())));
+    }
+    let sample = |i: usize| -> f64 {
+        let bytes = [body[4 * i], body[4 * i + 1], body[4 * i + 2], body[4 * i + 3]];
+        (if little_endian { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) }) as f64
+    };
+    let mut pixels: std::vec::Vec<Color> = std::vec::Vec::with_capacity(width * height);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let base = (y * width + x) * channels;
+            pixels.push(if channels == 3 {
+                color(sample(base), sample(base + 1), sample(base + 2))
+            } else {
+                color(sample(base), sample(base), sample(base))
+            });
+        }
+    }
+    Ok(image(width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::temp_file;
+
+    fn assert_close(c: Color, r: f64, g: f64, b: f64) {
+        assert!((c.r() - r).abs() < 1e-6 && (c.g() - g).abs() < 1e-6 && (c.b() - b).abs() < 1e-6,
+                "({}, {}, {}) is not ({}, {}, {})", c.r(), c.g(), c.b(), r, g, b);
+    }
+
+    fn error_message(result: Result<Image, LoadError>) -> String {
+        match result {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("expected an error")
+        }
+    }
+
+    #[test]
+    fn loads_ascii_ppm() {
+        let path = temp_file("ascii.ppm", b"P3\n# Two pixels\n2 1\n255\n255 0 0\n0 0 51 # dark blue\n");
+        let image = load_image_raw(&path).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_close(image.pixel(0, 0), 1.0, 0.0, 0.0);
+        assert_close(image.pixel(1, 0), 0.0, 0.0, 0.2);
+        // load_image undoes the gamma of written renders.
+        assert_close(load_image(&path).unwrap().pixel(1, 0), 0.0, 0.0, 0.04);
+    }
+
+    #[test]
+    fn loads_binary_ppm() {
+        let mut data = b"P6 1 2 255\n".to_vec();
+        data.extend([0, 128, 255, 255, 255, 255]);
+        let image = load_image_raw(&temp_file("binary.ppm", &data)).unwrap();
+        assert_close(image.pixel(0, 0), 0.0, 128.0 / 255.0, 1.0);
+        assert_close(image.pixel(0, 1), 1.0, 1.0, 1.0);
+
+        let mut data = b"P6 1 1 65535\n".to_vec();
+        data.extend([0, 0, 0x80, 0x00, 0xff, 0xff]);
+        let image = load_image_raw(&temp_file("wide.ppm", &data)).unwrap();
+        assert_close(image.pixel(0, 0), 0.0, 32768.0 / 65535.0, 1.0);
+    }
+
+    #[test]
+    fn loads_png() {
+        let path = temp_file("pixel.png", &[]);
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), 1, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(&[255, 0, 0]).unwrap();
+        assert_close(load_image(&path).unwrap().pixel(0, 0), 1.0, 0.0, 0.0);
+    }
+
+    #[test]
+    fn loads_pfm_bottom_up() {
+        let mut data = b"PF\n1 2\n-1.0\n".to_vec();
+        for value in [0.5f32, 1.0, 2.0, 4.0, 8.0, 16.0] {
+            data.extend(value.to_le_bytes());
+        }
+        let image = load_image(&temp_file("float.pfm", &data)).unwrap();
+        assert_close(image.pixel(0, 0), 4.0, 8.0, 16.0);
+        assert_close(image.pixel(0, 1), 0.5, 1.0, 2.0);
+    }
+
+    #[test]
+    fn loads_flat_and_run_length_encoded_hdr() {
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        // One pixel of 1.0 in every channel is mantissa 128 with exponent 129.
+        let mut flat = header.clone();
+        for _ in 0..8 {
+            flat.extend([128, 128, 128, 129]);
+        }
+        let image = load_image(&temp_file("flat.hdr", &flat)).unwrap();
+        assert_close(image.pixel(7, 0), 1.0, 1.0, 1.0);
+
+        let mut rle = header;
+        rle.extend([2, 2, 0, 8]);
+        for value in [128, 64, 128, 129] {
+            rle.extend([128 + 8, value]);
+        }
+        let image = load_image(&temp_file("rle.hdr", &rle)).unwrap();
+        assert_close(image.pixel(3, 0), 1.0, 0.5, 1.0);
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(error_message(load_image(&temp_file("p5.ppm", b"P5 1 1 255\n\0"))).contains("unsupported PPM type 'P5'"));
+        assert!(error_message(load_image(&temp_file("pg.pfm", b"PG 1 1 -1.0\n"))).contains("unsupported PFM type 'PG'"));
+        let hdr = b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0";
+        assert!(matches!(load_image(&temp_file("xyze.hdr", hdr)), Err(LoadError::Parse { line: 2, .. })));
+        assert!(load_image(&temp_file("not.png", b"P3 1 1 255 0 0 0")).is_err_and(|err| matches!(err, LoadError::Format { .. })));
+    }
+
+    #[test]
+    fn rejects_bad_samples() {
+        let path = temp_file("bad_sample.ppm", b"P3\n1 2\n255\n0 0 0\n0 x 0\n");
+        assert!(matches!(load_image(&path), Err(LoadError::Parse { line: 5, .. })));
+        let path = temp_file("bad_max.ppm", b"P3\n1 1\n0\n0 0 0\n");
+        assert!(matches!(load_image(&path), Err(LoadError::Parse { line: 3, .. })));
+    }
+
+    #[test]
+    fn rejects_truncated_bodies() {
+        assert!(error_message(load_image(&temp_file("short.ppm", b"P3 2 1 255\n0 0 0\n"))).contains("expected 6 samples"));
+        assert!(error_message(load_image(&temp_file("short_p6.ppm", b"P6 2 1 255\n\0\0\0"))).contains("expected 6 samples"));
+        assert!(error_message(load_image(&temp_file("short.pfm", b"PF 1 1 -1.0\n\0\0\0\0"))).contains("expected 3 samples"));
+        let hdr = b"#?RADIANCE\n\n-Y 2 +X 1\n\x80\x80\x80\x81";
+        assert!(error_message(load_image(&temp_file("short.hdr", hdr))).contains("truncated"));
+        assert!(error_message(load_image(&temp_file("header.ppm", b"P6 2"))).contains("end of file in header"));
+    }
+
+    #[test]
+    fn rejects_impossible_sizes() {
+        let path = temp_file("huge.ppm", b"P3 4294967296 4294967296 255\n");
+        assert!(error_message(load_image(&path)).contains("too large"));
+        let path = temp_file("huge.hdr", b"#?RADIANCE\n\n-Y 1 +X 4000000000\n\0\0\0\0");
+        assert!(error_message(load_image(&path)).contains("truncated"));
+        assert!(error_message(load_image(&temp_file("empty.ppm", b"P3 0 4 255\n"))).contains("no pixels"));
+        assert!(error_message(load_image(&temp_file("empty.pfm", b"PF 4 0 -1.0\n"))).contains("no pixels"));
+    }
+}
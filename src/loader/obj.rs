@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use crate::basic::{black, color, Color, Point, point, vec, Vec};
+use crate::hittable::{Mesh, mesh, MeshFace};
+use crate::material::{dielectics, diffuse_light, lambertian, metal, Scatter};
+use crate::texture::image::image_texture;
+use super::image::load_image;
+use super::{LoadError, parse_error, read_file};
+
+/// The subset of an MTL material that maps onto our materials.
+struct MtlMaterial {
+    diffuse: Color,   // Kd
+    specular: Color,  // Ks
+    emission: Color,  // Ke
+    shininess: f64,   // Ns
+    ior: f64,         // Ni
+    dissolve: f64,    // d, or 1 - Tr
+    illum: i32,
+    diffuse_map: Option<String> // map_Kd, resolved relative to the MTL file
+}
+
+fn default_mtl() -> MtlMaterial {
+    MtlMaterial { diffuse: color(0.8, 0.8, 0.8), specular: black(), emission: black(), shininess: 0.0, ior: 1.5, dissolve: 1.0, illum: 2, diffuse_map: None }
+}
+
+fn max_component(c: &Color) -> f64 { c.r().max(c.g()).max(c.b()) }
+
+impl MtlMaterial {
+    /// Pick the closest of our materials: any emission makes a light, transparent or refracting
+    /// illumination models become glass, a dominant specular term becomes metal with a fuzz derived
+    /// from the Phong exponent, anything else is diffuse.
+    fn to_material(&self) -> Result<Arc<dyn Scatter + Sync + Send>, LoadError> {
+        let ret: Arc<dyn Scatter + Sync + Send> = if max_component(&self.emission) > 0.0 {
+            Arc::new(diffuse_light::diffuse_light(self.emission))
+        } else if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Arc::new(dielectics::dielectrics(self.ior))
+        } else if max_component(&self.specular) > 0.0 && (self.illum == 3 || max_component(&self.specular) >= max_component(&self.diffuse)) {
+            let fuzz = if self.shininess > 0.0 { (2.0 / (self.shininess + 2.0)).sqrt() } else { 0.0 };
+            Arc::new(metal::metal(self.specular, fuzz))
+        } else if let Some(map) = &self.diffuse_map {
+            let texture = image_texture(Arc::new(load_image(map)?));
+            Arc::new(lambertian::lambertian_texture(Arc::new(texture)))
+        } else {
+            Arc::new(lambertian::lambertian(self.diffuse))
+        };
+        Ok(ret)
+    }
+}
+
+/// Split a line into whitespace separated tokens, dropping comments.
+fn tokenize(line: &str) -> std::vec::Vec<&str> {
+    line.split('#').next().unwrap_or("").split_whitespace().collect()
+}
+
+fn parse_floats<const N: usize>(args: &[&str], file: &str, line: usize, keyword: &str) -> Result<[f64; N], LoadError> {
+    if args.len() < N {
+        return Err(parse_error(file, line, format!("'{}' expects {} numbers, found {}", keyword, N, args.len())));
+    }
+    let mut ret = [0.0; N];
+    for (value, arg) in ret.iter_mut().zip(args) {
+        *value = arg.parse::<f64>().map_err(|_| parse_error(file, line, format!("'{}' is not a number", arg)))?;
+    }
+    Ok(ret)
+}
+
+fn parse_color(args: &[&str], file: &str, line: usize, keyword: &str) -> Result<Color, LoadError> {
+    let [r, g, b] = parse_floats::<3>(args, file, line, keyword)?;
+    Ok(color(r, g, b))
+}
+
+fn parse_mtl(path: &Path, materials: &mut HashMap<String, MtlMaterial>) -> Result<(), LoadError> {
+    let file = path.display().to_string();
+    let content = read_file(&file)?;
+    let mut current: Option<String> = None;
+    for (index, raw_line) in content.lines().enumerate() {
+        let line = index + 1;
+        let tokens = tokenize(raw_line);
+        let Some((&keyword, args)) = tokens.split_first() else { continue; };
+        if keyword == "newmtl" {
+            let name = args.join(" ");
+            if name.is_empty() {
+                return Err(parse_error(&file, line, "'newmtl' without a name".to_string()));
+            }
+            materials.insert(name.clone(), default_mtl());
+            current = Some(name);
+            continue;
+        }
+        let Some(mat) = current.as_ref().and_then(|name| materials.get_mut(name)) else {
+            return Err(parse_error(&file, line, format!("'{}' before any 'newmtl'", keyword)));
+        };
+        match keyword {
+            "Kd" => mat.diffuse = parse_color(args, &file, line, keyword)?,
+            "Ks" => mat.specular = parse_color(args, &file, line, keyword)?,
+            "Ke" => mat.emission = parse_color(args, &file, line, keyword)?,
+            "Ns" => mat.shininess = parse_floats::<1>(args, &file, line, keyword)?[0],
+            "Ni" => mat.ior = parse_floats::<1>(args, &file, line, keyword)?[0],
+            "d" => mat.dissolve = parse_floats::<1>(args, &file, line, keyword)?[0],
+            "Tr" => mat.dissolve = 1.0 - parse_floats::<1>(args, &file, line, keyword)?[0],
+            "illum" => mat.illum = parse_floats::<1>(args, &file, line, keyword)?[0] as i32,
+            "map_Kd" => {
+                // Options such as -s or -o are not supported, the file name comes last.
+                let Some(name) = args.last() else {
+                    return Err(parse_error(&file, line, "'map_Kd' without a file name".to_string()));
+                };
+                let dir = path.parent().unwrap_or(Path::new(""));
+                mat.diffuse_map = Some(dir.join(name).display().to_string());
+            }
+            _ => {} // Other texture maps and statements are not supported
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a 1-based (or negative, relative to the end) OBJ index into a 0-based one.
+fn resolve_index(token: &str, count: usize, file: &str, line: usize) -> Result<usize, LoadError> {
+    let index = token.parse::<i64>().map_err(|_| parse_error(file, line, format!("'{}' is not an index", token)))?;
+    let resolved = if index > 0 { index - 1 } else { count as i64 + index };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(parse_error(file, line, format!("index {} out of range (have {})", index, count)));
+    }
+    Ok(resolved as usize)
+}
+
+/// Load a Wavefront OBJ file into a shared triangle mesh. Polygons are fan-triangulated and
+/// materials from referenced MTL files are mapped onto Lambertian, Metal and Dielectrics. Faces
+/// without a material use default_mat.
+pub fn load_obj(path: &str, default_mat: Arc<dyn Scatter + Sync + Send>) -> Result<Mesh, LoadError> {
+    let content = read_file(path)?;
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut positions: std::vec::Vec<Point> = std::vec::Vec::new();
+    let mut normals: std::vec::Vec<Vec> = std::vec::Vec::new();
+    let mut uvs: std::vec::Vec<(f64, f64)> = std::vec::Vec::new();
+    let mut faces: std::vec::Vec<MeshFace> = std::vec::Vec::new();
+    let mut mtl_materials: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut materials: std::vec::Vec<Arc<dyn Scatter + Sync + Send>> = std::vec::Vec::from([default_mat]);
+    let mut material_ids: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    for (index, raw_line) in content.lines().enumerate() {
+        let line = index + 1;
+        let tokens = tokenize(raw_line);
+        let Some((&keyword, args)) = tokens.split_first() else { continue; };
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats::<3>(args, path, line, keyword)?;
+                positions.push(point(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats::<3>(args, path, line, keyword)?;
+                normals.push(vec(x, y, z));
+            }
+            "vt" => {
+                let u = parse_floats::<1>(args, path, line, keyword)?[0];
+                let v = if args.len() > 1 { parse_floats::<1>(&args[1..], path, line, keyword)?[0] } else { 0.0 };
+                uvs.push((u, v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parse_error(path, line, format!("face needs at least 3 vertices, found {}", args.len())));
+                }
+                let mut corners = std::vec::Vec::with_capacity(args.len());
+                for arg in args {
+                    let parts: std::vec::Vec<&str> = arg.split('/').collect();
+                    if parts.len() > 3 {
+                        return Err(parse_error(path, line, format!("malformed face vertex '{}'", arg)));
+                    }
+                    let v = resolve_index(parts[0], positions.len(), path, line)?;
+                    let vt = match parts.get(1) {
+                        Some(s) if !s.is_empty() => Some(resolve_index(s, uvs.len(), path, line)?),
+                        _ => None
+                    };
+                    let vn = match parts.get(2) {
+                        Some(s) if !s.is_empty() => Some(resolve_index(s, normals.len(), path, line)?),
+                        _ => None
+                    };
+                    corners.push((v, vt, vn));
+                }
+                let uv_indices: Option<std::vec::Vec<usize>> = corners.iter().map(|c| c.1).collect();
+                let normal_indices: Option<std::vec::Vec<usize>> = corners.iter().map(|c| c.2).collect();
+                for i in 1..corners.len() - 1 {
+                    let fan = [0, i, i + 1];
+                    faces.push(MeshFace {
+                        vertices: fan.map(|k| corners[k].0),
+                        normals: normal_indices.as_ref().map(|n| fan.map(|k| n[k])),
+                        uvs: uv_indices.as_ref().map(|t| fan.map(|k| t[k])),
+                        material: current_material
+                    });
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(parse_error(path, line, "'mtllib' without a file name".to_string()));
+                }
+                for name in args {
+                    parse_mtl(&base_dir.join(name), &mut mtl_materials)?;
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                current_material = if let Some(&id) = material_ids.get(&name) {
+                    id
+                } else if let Some(mtl) = mtl_materials.get(&name) {
+                    materials.push(mtl.to_material()?);
+                    material_ids.insert(name, materials.len() - 1);
+                    materials.len() - 1
+                } else {
+                    return Err(parse_error(path, line, format!("unknown material '{}'", name)));
+                };
+            }
+            _ => {} // Groups, smoothing groups, lines and free-form geometry are ignored
+        }
+    }
+    Ok(mesh(positions, normals, uvs, faces, materials, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::{interval, ray};
+    use crate::constants::INFINITY;
+    use crate::hittable::{Hit, mesh_triangles};
+    use crate::loader::temp_file;
+
+    fn default_material() -> Arc<dyn Scatter + Sync + Send> { Arc::new(lambertian::lambertian(color(0.5, 0.5, 0.5))) }
+
+    fn parse_error_line(result: Result<Mesh, LoadError>) -> usize {
+        match result {
+            Err(LoadError::Parse { line, .. }) => line,
+            Err(err) => panic!("expected a parse error, got {}", err),
+            Ok(_) => panic!("expected a parse error")
+        }
+    }
+
+    #[test]
+    fn loads_polygons_with_materials() {
+        temp_file("valid.mtl", b"newmtl red\nKd 0.8 0.1 0.1\nnewmtl glass\nNi 1.5\nillum 7\n");
+        let path = temp_file("valid.obj", b"# A quad and a triangle\nmtllib valid.mtl\n\
+            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\n\
+            usemtl red\nf 1/1/1 2/2/1 3/3/1 4/4/1\nusemtl glass\nf -4 -3 -2\n");
+        let mesh = load_obj(&path, default_material()).unwrap();
+        assert_eq!(mesh.vertex_count(), 4);
+        assert_eq!(mesh.face_count(), 3);
+    }
+
+    #[test]
+    fn assigns_mtl_colors_to_the_faces_after_usemtl() {
+        temp_file("colors.mtl", b"newmtl red\nKd 0.8 0.1 0.1\nnewmtl lamp\nKe 4 3 2\n");
+        let path = temp_file("colors.obj", b"mtllib colors.mtl\n\
+            v 0 0 0\nv 1 0 0\nv 0 1 0\nv 2 0 0\nv 3 0 0\nv 2 1 0\nv 4 0 0\nv 5 0 0\nv 4 1 0\n\
+            f 1 2 3\nusemtl red\nf 4 5 6\nusemtl lamp\nf 7 8 9\n");
+        let triangles = mesh_triangles(&Arc::new(load_obj(&path, default_material()).unwrap()));
+        let hit_at = |x: f64| {
+            let r = ray(point(x, 0.25, 1.0), vec(0.0, 0.0, -1.0), 0.0);
+            triangles.hit(&r, interval(0.001, INFINITY)).expect("Rays aim at the middle of a face")
+        };
+        let channels = |c: Color| [c.r(), c.g(), c.b()];
+        let first = hit_at(0.25);
+        assert_eq!(channels(first.mat.albedo(&first)), [0.5, 0.5, 0.5]);
+        let red = hit_at(2.25);
+        assert_eq!(channels(red.mat.albedo(&red)), [0.8, 0.1, 0.1]);
+        assert_eq!(channels(red.mat.emitted(red.u, red.v, &red.p)), [0.0, 0.0, 0.0]);
+        let lamp = hit_at(4.25);
+        assert_eq!(channels(lamp.mat.emitted(lamp.u, lamp.v, &lamp.p)), [4.0, 3.0, 2.0]);
+    }
+
+    #[test]
+    fn rejects_index_out_of_range() {
+        let path = temp_file("bad_index.obj", b"v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n");
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), 4);
+    }
+
+    #[test]
+    fn rejects_zero_index() {
+        let path = temp_file("zero_index.obj", b"v 0 0 0\nv 1 0 0\nv 1 1 0\nf 0 1 2\n");
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), 4);
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        let path = temp_file("bad_number.obj", b"v 0 0 0\nv 1 zero 0\n");
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), 2);
+        let path = temp_file("short_vertex.obj", b"v 0 0\n");
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), 1);
+    }
+
+    #[test]
+    fn rejects_degenerate_face() {
+        let path = temp_file("two_vertices.obj", b"v 0 0 0\nv 1 0 0\nf 1 2\n");
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), 3);
+    }
+
+    #[test]
+    fn rejects_unknown_material() {
+        let path = temp_file("unknown_material.obj", b"v 0 0 0\nusemtl missing\n");
+        assert_eq!(parse_error_line(load_obj(&path, default_material())), 2);
+    }
+
+    #[test]
+    fn reports_mtl_errors_with_their_own_file() {
+        temp_file("orphan.mtl", b"# No newmtl yet\nKd 1 1 1\n");
+        let path = temp_file("orphan.obj", b"mtllib orphan.mtl\n");
+        match load_obj(&path, default_material()) {
+            Err(LoadError::Parse { file, line, .. }) => {
+                assert!(file.ends_with("orphan.mtl"));
+                assert_eq!(line, 2);
+            }
+            _ => panic!("expected a parse error in the MTL file")
+        }
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        assert!(matches!(load_obj("/nonexistent/scene.obj", default_material()), Err(LoadError::Io(..))));
+    }
+}
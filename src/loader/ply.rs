@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::basic::{color, Point, point, vec, Vec};
+use crate::hittable::{Mesh, mesh, MeshFace};
+use crate::material::{lambertian, Scatter};
+use super::{format_error, LoadError, parse_error};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64
+}
+
+fn scalar_type(name: &str) -> Option<ScalarType> {
+    match name {
+        "char" | "int8" => Some(ScalarType::Int8),
+        "uchar" | "uint8" => Some(ScalarType::UInt8),
+        "short" | "int16" => Some(ScalarType::Int16),
+        "ushort" | "uint16" => Some(ScalarType::UInt16),
+        "int" | "int32" => Some(ScalarType::Int32),
+        "uint" | "uint32" => Some(ScalarType::UInt32),
+        "float" | "float32" => Some(ScalarType::Float32),
+        "double" | "float64" => Some(ScalarType::Float64),
+        _ => None
+    }
+}
+
+impl ScalarType {
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    value_type: ScalarType,
+    list_count_type: Option<ScalarType> // Set for list properties
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: std::vec::Vec<Property>
+}
+
+struct Header {
+    format: Format,
+    elements: std::vec::Vec<Element>,
+    lines: usize, // Number of lines including end_header
+    body_offset: usize
+}
+
+fn parse_header(file: &str, data: &[u8]) -> Result<Header, LoadError> {
+    let mut format = None;
+    let mut elements: std::vec::Vec<Element> = std::vec::Vec::new();
+    let mut offset = 0;
+    let mut line = 0;
+    loop {
+        let Some(end) = data[offset..].iter().position(|&c| c == b'\n') else {
+            return Err(format_error(file, "header is not terminated by 'end_header'".to_string()));
+        };
+        line += 1;
+        let text = String::from_utf8_lossy(&data[offset..offset + end]).to_string();
+        offset += end + 1;
+        let tokens: std::vec::Vec<&str> = text.split_whitespace().collect();
+        if line == 1 {
+            if tokens.first() != Some(&"ply") {
+                return Err(parse_error(file, line, "missing 'ply' magic number".to_string()));
+            }
+            continue;
+        }
+        let Some((&keyword, args)) = tokens.split_first() else { continue; };
+        match keyword {
+            "format" => {
+                format = Some(match args.first() {
+                    Some(&"ascii") => Format::Ascii,
+                    Some(&"binary_little_endian") => Format::BinaryLittleEndian,
+                    Some(&"binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(parse_error(file, line, format!("unknown format '{}'", args.join(" "))))
+                });
+            }
+            "element" => {
+                if args.len() != 2 {
+                    return Err(parse_error(file, line, "'element' expects a name and a count".to_string()));
+                }
+                let count = args[1].parse::<usize>()
+                    .map_err(|_| parse_error(file, line, format!("'{}' is not an element count", args[1])))?;
+                elements.push(Element { name: args[0].to_string(), count, properties: std::vec::Vec::new() });
+            }
+            "property" => {
+                let Some(element) = elements.last_mut() else {
+                    return Err(parse_error(file, line, "'property' before any 'element'".to_string()));
+                };
+                let unknown_type = |name: &str| parse_error(file, line, format!("unknown property type '{}'", name));
+                let property = if args.first() == Some(&"list") {
+                    if args.len() != 4 {
+                        return Err(parse_error(file, line, "list property expects count type, item type and name".to_string()));
+                    }
+                    Property {
+                        name: args[3].to_string(),
+                        value_type: scalar_type(args[2]).ok_or_else(|| unknown_type(args[2]))?,
+                        list_count_type: Some(scalar_type(args[1]).ok_or_else(|| unknown_type(args[1]))?)
+                    }
+                } else {
+                    if args.len() != 2 {
+                        return Err(parse_error(file, line, "property expects a type and a name".to_string()));
+                    }
+                    Property {
+                        name: args[1].to_string(),
+                        value_type: scalar_type(args[0]).ok_or_else(|| unknown_type(args[0]))?,
+                        list_count_type: None
+                    }
+                };
+                element.properties.push(property);
+            }
+            "end_header" => break,
+            "comment" | "obj_info" => {}
+            _ => return Err(parse_error(file, line, format!("unexpected header keyword '{}'", keyword)))
+        }
+    }
+    let Some(format) = format else {
+        return Err(format_error(file, "header has no 'format' line".to_string()));
+    };
+    Ok(Header { format, elements, lines: line, body_offset: offset })
+}
+
+/// Source of scalar values for the element data, either ASCII tokens or packed binary.
+trait ValueReader {
+    /// Called at the start of each element instance.
+    fn next_instance(&mut self) -> Result<(), LoadError>;
+    fn read(&mut self, value_type: ScalarType) -> Result<f64, LoadError>;
+}
+
+struct AsciiReader<'a> {
+    file: &'a str,
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    first_line: usize,
+    line: usize,
+    tokens: std::vec::IntoIter<&'a str>
+}
+
+impl ValueReader for AsciiReader<'_> {
+    fn next_instance(&mut self) -> Result<(), LoadError> {
+        loop {
+            let Some((index, text)) = self.lines.next() else {
+                return Err(format_error(self.file, "unexpected end of file".to_string()));
+            };
+            self.line = self.first_line + index;
+            let tokens: std::vec::Vec<&str> = text.split_whitespace().collect();
+            if !tokens.is_empty() {
+                self.tokens = tokens.into_iter();
+                return Ok(());
+            }
+        }
+    }
+    fn read(&mut self, value_type: ScalarType) -> Result<f64, LoadError> {
+        let Some(token) = self.tokens.next() else {
+            return Err(parse_error(self.file, self.line, "too few values".to_string()));
+        };
+        let value = token.parse::<f64>().map_err(|_| parse_error(self.file, self.line, format!("'{}' is not a number", token)))?;
+        if value_type != ScalarType::Float32 && value_type != ScalarType::Float64 && value.fract() != 0.0 {
+            return Err(parse_error(self.file, self.line, format!("'{}' is not an integer", token)));
+        }
+        Ok(value)
+    }
+}
+
+struct BinaryReader<'a> {
+    file: &'a str,
+    data: &'a [u8],
+    offset: usize,
+    big_endian: bool
+}
+
+impl ValueReader for BinaryReader<'_> {
+    fn next_instance(&mut self) -> Result<(), LoadError> { Ok(()) }
+    fn read(&mut self, value_type: ScalarType) -> Result<f64, LoadError> {
+        let size = value_type.size();
+        if self.offset + size > self.data.len() {
+            return Err(format_error(self.file, format!("unexpected end of file at byte {}", self.offset)));
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(&self.data[self.offset..self.offset + size]);
+        if self.big_endian {
+            bytes[..size].reverse();
+        }
+        self.offset += size;
+        Ok(match value_type {
+            ScalarType::Int8 => bytes[0] as i8 as f64,
+            ScalarType::UInt8 => bytes[0] as f64,
+            ScalarType::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            ScalarType::Int32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            ScalarType::UInt32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            ScalarType::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(bytes)
+        })
+    }
+}
+
+/// Load a PLY file (ASCII or binary, either endianness) into a shared triangle mesh. Vertex
+/// normals and texture coordinates are used when present. Per-vertex colors become Lambertian
+/// materials averaged over each face, otherwise every face uses mat.
+pub fn load_ply(path: &str, mat: Arc<dyn Scatter + Sync + Send>) -> Result<Mesh, LoadError> {
+    let data = std::fs::read(path).map_err(|err| LoadError::Io(path.to_string(), err))?;
+    let header = parse_header(path, &data)?;
+    match header.format {
+        Format::Ascii => {
+            let body = std::str::from_utf8(&data[header.body_offset..])
+                .map_err(|_| format_error(path, "ASCII body is not valid UTF-8".to_string()))?;
+            let mut reader = AsciiReader {
+                file: path,
+                lines: body.lines().enumerate(),
+                first_line: header.lines + 1,
+                line: header.lines,
+                tokens: std::vec::Vec::new().into_iter()
+            };
+            read_body(path, &header, &mut reader, mat)
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+            let mut reader = BinaryReader {
+                file: path,
+                data: &data,
+                offset: header.body_offset,
+                big_endian: header.format == Format::BinaryBigEndian
+            };
+            read_body(path, &header, &mut reader, mat)
+        }
+    }
+}
+
+/// A list length or vertex index, None unless value is a non-negative integer.
+fn to_index(value: f64) -> Option<usize> {
+    (value.is_finite() && value >= 0.0 && value.fract() == 0.0).then_some(value as usize)
+}
+
+fn read_body<R: ValueReader>(file: &str, header: &Header, reader: &mut R, mat: Arc<dyn Scatter + Sync + Send>) -> Result<Mesh, LoadError> {
+    let mut positions: std::vec::Vec<Point> = std::vec::Vec::new();
+    let mut normals: std::vec::Vec<Vec> = std::vec::Vec::new();
+    let mut uvs: std::vec::Vec<(f64, f64)> = std::vec::Vec::new();
+    let mut colors: std::vec::Vec<[f64; 3]> = std::vec::Vec::new();
+    let mut polygons: std::vec::Vec<std::vec::Vec<usize>> = std::vec::Vec::new();
+
+    for element in &header.elements {
+        let position = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let find = |names: [&str; 3]| -> Option<[usize; 3]> {
+            Some([position(names[0])?, position(names[1])?, position(names[2])?])
+        };
+        let xyz = find(["x", "y", "z"]);
+        let normal = find(["nx", "ny", "nz"]);
+        let rgb = find(["red", "green", "blue"]).or_else(|| find(["r", "g", "b"]));
+        let uv = [("u", "v"), ("s", "t"), ("texture_u", "texture_v")].iter()
+            .find_map(|(u, v)| Some([position(u)?, position(v)?]));
+        let indices = position("vertex_indices").or_else(|| position("vertex_index"));
+
+        let mut values = vec![0.0; element.properties.len()];
+        for instance in 0..element.count {
+            reader.next_instance()?;
+            let mut list = std::vec::Vec::new();
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.list_count_type {
+                    Some(count_type) => {
+                        let value = reader.read(count_type)?;
+                        let count = to_index(value).ok_or_else(|| {
+                            format_error(file, format!("{} {} has a list of length {}", element.name, instance, value))
+                        })?;
+                        // Not preallocated, a corrupt count runs into the end of the data instead.
+                        let mut items = std::vec::Vec::new();
+                        for _ in 0..count {
+                            items.push(reader.read(property.value_type)?);
+                        }
+                        if Some(i) == indices {
+                            list = items;
+                        }
+                    }
+                    None => values[i] = reader.read(property.value_type)?
+                }
+            }
+            if element.name == "vertex" {
+                let Some([x, y, z]) = xyz else {
+                    return Err(format_error(file, "vertex element has no x, y, z properties".to_string()));
+                };
+                positions.push(point(values[x], values[y], values[z]));
+                if let Some([x, y, z]) = normal {
+                    normals.push(vec(values[x], values[y], values[z]));
+                }
+                if let Some([u, v]) = uv {
+                    uvs.push((values[u], values[v]));
+                }
+                if let Some(rgb) = rgb {
+                    // Integer channels are in [0, 255], floating point ones in [0, 1].
+                    colors.push(rgb.map(|c| match element.properties[c].value_type {
+                        ScalarType::Float32 | ScalarType::Float64 => values[c],
+                        _ => values[c] / 255.0
+                    }));
+                }
+            } else if element.name == "face" && indices.is_some() {
+                let polygon = list.iter().map(|&i| {
+                    to_index(i).ok_or_else(|| format_error(file, format!("face {} has vertex index {}", polygons.len(), i)))
+                }).collect::<Result<_, _>>()?;
+                polygons.push(polygon);
+            }
+        }
+    }
+
+    let mut materials: std::vec::Vec<Arc<dyn Scatter + Sync + Send>> = std::vec::Vec::from([mat]);
+    let mut color_ids: HashMap<[u8; 3], usize> = HashMap::new();
+    let mut faces = std::vec::Vec::new();
+    for (index, polygon) in polygons.iter().enumerate() {
+        if polygon.len() < 3 {
+            return Err(format_error(file, format!("face {} has only {} vertices", index, polygon.len())));
+        }
+        if let Some(&bad) = polygon.iter().find(|&&i| i >= positions.len()) {
+            return Err(format_error(file, format!("face {} references vertex {} of {}", index, bad, positions.len())));
+        }
+        let material = if colors.is_empty() {
+            0
+        } else {
+            // Faces sharing the same 8-bit average color share one material.
+            let mut sum = [0.0; 3];
+            for &i in polygon {
+                for (channel, value) in sum.iter_mut().zip(colors[i]) {
+                    *channel += value;
+                }
+            }
+            let key = sum.map(|c| (c / polygon.len() as f64 * 255.0).round().clamp(0.0, 255.0) as u8);
+            *color_ids.entry(key).or_insert_with(|| {
+                let albedo = color(key[0] as f64 / 255.0, key[1] as f64 / 255.0, key[2] as f64 / 255.0);
+                materials.push(Arc::new(lambertian::lambertian(albedo)));
+                materials.len() - 1
+            })
+        };
+        for i in 1..polygon.len() - 1 {
+            let tri = [polygon[0], polygon[i], polygon[i + 1]];
+            faces.push(MeshFace {
+                vertices: tri,
+                normals: if normals.is_empty() { None } else { Some(tri) },
+                uvs: if uvs.is_empty() { None } else { Some(tri) },
+                material
+            });
+        }
+    }
+    Ok(mesh(positions, normals, uvs, faces, materials, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::{interval, ray};
+    use crate::constants::INFINITY;
+    use crate::hittable::{Hit, mesh_triangles};
+    use crate::loader::temp_file;
+
+    fn default_material() -> Arc<dyn Scatter + Sync + Send> { Arc::new(lambertian::lambertian(color(0.5, 0.5, 0.5))) }
+
+    const ASCII_QUAD: &str = "ply\nformat ascii 1.0\ncomment A unit quad\nelement vertex 4\n\
+        property float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+        0 0 0 255 0 0\n1 0 0 255 0 0\n1 1 0 0 255 0\n0 1 0 0 255 0\n4 0 1 2 3\n";
+
+    /// A binary triangle with uint32 list counts and int32 indices, the face given as raw bytes.
+    fn binary_triangle(big_endian: bool, face: &[u8]) -> std::vec::Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut data = format!("ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uint int vertex_indices\nend_header\n", format).into_bytes();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            data.extend(if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+        }
+        data.extend(face);
+        data
+    }
+
+    fn face_bytes(big_endian: bool, count: u32, indices: &[i32]) -> std::vec::Vec<u8> {
+        let mut data = std::vec::Vec::from(if big_endian { count.to_be_bytes() } else { count.to_le_bytes() });
+        for index in indices {
+            data.extend(if big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+        }
+        data
+    }
+
+    fn error_message(result: Result<Mesh, LoadError>) -> String {
+        match result {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("expected an error")
+        }
+    }
+
+    #[test]
+    fn loads_ascii_with_colors() {
+        let path = temp_file("quad.ply", ASCII_QUAD.as_bytes());
+        let mesh = load_ply(&path, default_material()).unwrap();
+        assert_eq!(mesh.vertex_count(), 4);
+        assert_eq!(mesh.face_count(), 2);
+    }
+
+    #[test]
+    fn turns_vertex_colors_into_face_materials() {
+        let path = temp_file("colored.ply", b"ply\nformat ascii 1.0\nelement vertex 6\n\
+            property float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 2\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0 255 0 0\n1 0 0 255 0 0\n0 1 0 255 0 0\n2 0 0 0 0 255\n3 0 0 0 0 255\n2 1 0 0 255 255\n3 0 1 2\n3 3 4 5\n");
+        let triangles = mesh_triangles(&Arc::new(load_ply(&path, default_material()).unwrap()));
+        let albedo_at = |x: f64| {
+            let r = ray(point(x, 0.25, 1.0), vec(0.0, 0.0, -1.0), 0.0);
+            let rec = triangles.hit(&r, interval(0.001, INFINITY)).expect("Rays aim at the middle of a face");
+            let albedo = rec.mat.albedo(&rec);
+            [albedo.r(), albedo.g(), albedo.b()]
+        };
+        assert_eq!(albedo_at(0.25), [1.0, 0.0, 0.0]);
+        // The second face averages two blue vertices and a cyan one.
+        assert_eq!(albedo_at(2.25), [0.0, 85.0 / 255.0, 1.0]);
+    }
+
+    #[test]
+    fn loads_binary_in_both_byte_orders() {
+        for big_endian in [false, true] {
+            let path = temp_file(&format!("triangle_{}.ply", big_endian), &binary_triangle(big_endian, &face_bytes(big_endian, 3, &[0, 1, 2])));
+            let mesh = load_ply(&path, default_material()).unwrap();
+            assert_eq!(mesh.vertex_count(), 3);
+            assert_eq!(mesh.face_count(), 1);
+        }
+    }
+
+    #[test]
+    fn rejects_index_out_of_range() {
+        let path = temp_file("out_of_range.ply", &binary_triangle(false, &face_bytes(false, 3, &[0, 1, 3])));
+        assert!(error_message(load_ply(&path, default_material())).contains("face 0 references vertex 3"));
+    }
+
+    #[test]
+    fn rejects_negative_index() {
+        let path = temp_file("negative.ply", &binary_triangle(false, &face_bytes(false, 3, &[-1, 1, 2])));
+        assert!(error_message(load_ply(&path, default_material())).contains("face 0 has vertex index -1"));
+        let path = temp_file("negative_ascii.ply", ASCII_QUAD.replace("4 0 1 2 3", "3 0 -1 2").as_bytes());
+        assert!(error_message(load_ply(&path, default_material())).contains("face 0 has vertex index -1"));
+    }
+
+    #[test]
+    fn rejects_truncated_body() {
+        let path = temp_file("truncated.ply", &binary_triangle(false, &face_bytes(false, 3, &[0, 1])));
+        assert!(error_message(load_ply(&path, default_material())).contains("unexpected end of file"));
+        let path = temp_file("truncated_ascii.ply", ASCII_QUAD.replace("4 0 1 2 3\n", "").as_bytes());
+        assert!(error_message(load_ply(&path, default_material())).contains("unexpected end of file"));
+    }
+
+    #[test]
+    fn rejects_huge_list_count_without_allocating() {
+        let path = temp_file("huge_count.ply", &binary_triangle(false, &face_bytes(false, u32::MAX, &[0, 1, 2])));
+        assert!(error_message(load_ply(&path, default_material())).contains("unexpected end of file"));
+    }
+
+    #[test]
+    fn rejects_unknown_format() {
+        let path = temp_file("unknown_format.ply", ASCII_QUAD.replace("format ascii", "format binary_middle_endian").as_bytes());
+        match load_ply(&path, default_material()) {
+            Err(LoadError::Parse { line, message, .. }) => {
+                assert_eq!(line, 2);
+                assert!(message.contains("unknown format"));
+            }
+            _ => panic!("expected a parse error")
+        }
+    }
+
+    #[test]
+    fn rejects_broken_headers() {
+        let path = temp_file("no_magic.ply", ASCII_QUAD.replacen("ply", "obj", 1).as_bytes());
+        assert!(matches!(load_ply(&path, default_material()), Err(LoadError::Parse { line: 1, .. })));
+        let path = temp_file("no_end.ply", b"ply\nformat ascii 1.0\nelement vertex 0\n");
+        assert!(error_message(load_ply(&path, default_material())).contains("end_header"));
+        let path = temp_file("bad_type.ply", ASCII_QUAD.replace("property float y", "property quad y").as_bytes());
+        assert!(matches!(load_ply(&path, default_material()), Err(LoadError::Parse { line: 6, .. })));
+    }
+
+    #[test]
+    fn reports_ascii_errors_with_their_line() {
+        let path = temp_file("bad_value.ply", ASCII_QUAD.replace("1 1 0 0 255 0", "1 one 0 0 255 0").as_bytes());
+        assert!(matches!(load_ply(&path, default_material()), Err(LoadError::Parse { line: 16, .. })));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::hittable::{VoxelGrid, voxel_grid};
+use super::{format_error, LoadError, parse_error, read_file};
+
+/// Number of voxels of an nx by ny by nz grid, an error if any size is zero or the count overflows.
+fn voxel_count(path: &str, nx: usize, ny: usize, nz: usize) -> Result<usize, LoadError> {
+    if nx == 0 || ny == 0 || nz == 0 {
+        return Err(format_error(path, format!("grid size {}x{}x{} has no voxels", nx, ny, nz)));
+    }
+    nx.checked_mul(ny).and_then(|count| count.checked_mul(nz))
+        .ok_or_else(|| format_error(path, format!("grid size {}x{}x{} is too large", nx, ny, nz)))
+}
+
+/// Load a voxel grid from a text file: a header line "nx ny nz" followed by nx * ny * nz numbers
+/// in any layout, x varying fastest, then y, then z. Everything after a '#' is a comment.
+pub fn load_voxel_text(path: &str) -> Result<VoxelGrid, LoadError> {
+    let content = read_file(path)?;
+    let mut dims: std::vec::Vec<usize> = std::vec::Vec::new();
+    let mut values: std::vec::Vec<f64> = std::vec::Vec::new();
+    for (index, raw_line) in content.lines().enumerate() {
+        let line = index + 1;
+        for token in raw_line.split('#').next().unwrap_or("").split_whitespace() {
+            if dims.len() < 3 {
+                let n = token.parse::<usize>().map_err(|_| parse_error(path, line, format!("'{}' is not a grid size", token)))?;
+                if n == 0 {
+                    return Err(parse_error(path, line, "grid sizes must be positive".to_string()));
+                }
+                dims.push(n);
+            } else {
+                let value = token.parse::<f64>().map_err(|_| parse_error(path, line, format!("'{}' is not a number", token)))?;
+                values.push(value);
+            }
+        }
+    }
+    if dims.len() < 3 {
+        return Err(format_error(path, "missing 'nx ny nz' header".to_string()));
+    }
+    let count = voxel_count(path, dims[0], dims[1], dims[2])?;
+    if values.len() != count {
+        return Err(format_error(path, format!("expected {} values, found {}", count, values.len())));
+    }
+    Ok(voxel_grid(dims[0], dims[1], dims[2], values))
+}
+
+/// Load a headerless grid of little endian 32-bit floats, x varying fastest, then y, then z.
+pub fn load_voxel_raw(path: &str, nx: usize, ny: usize, nz: usize) -> Result<VoxelGrid, LoadError> {
+    let bytes = voxel_count(path, nx, ny, nz)?.checked_mul(4)
+        .ok_or_else(|| format_error(path, format!("grid size {}x{}x{} is too large", nx, ny, nz)))?;
+    let data = std::fs::read(path).map_err(|err| LoadError::Io(path.to_string(), err))?;
+    if data.len() != bytes {
+        return Err(format_error(path, format!("expected {} bytes for a {}x{}x{} grid, found {}", bytes, nx, ny, nz, data.len())));
+    }
+    let values = data.chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64).collect();
+    Ok(voxel_grid(nx, ny, nz, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::point;
+    use crate::loader::temp_file;
+
+    fn error_message(result: Result<VoxelGrid, LoadError>) -> String {
+        match result {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("expected an error")
+        }
+    }
+
+    #[test]
+    fn loads_text_grids() {
+        let path = temp_file("grid.txt", b"# A 2x1x2 grid\n2 1 2\n0 1\n2 3 # last row\n");
+        let grid = load_voxel_text(&path).unwrap();
+        assert_eq!(grid.dimensions(), (2, 1, 2));
+        assert_eq!(grid.max_value(), 3.0);
+        assert_eq!(grid.sample(&point(1.0, 0.5, 0.0)), 1.0);
+    }
+
+    #[test]
+    fn loads_raw_grids() {
+        let data: std::vec::Vec<u8> = [0.5f32, 1.5, 2.5].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let path = temp_file("grid.raw", &data);
+        let grid = load_voxel_raw(&path, 3, 1, 1).unwrap();
+        assert_eq!(grid.dimensions(), (3, 1, 1));
+        assert_eq!(grid.max_value(), 2.5);
+    }
+
+    #[test]
+    fn rejects_size_mismatches() {
+        let path = temp_file("short.txt", b"2 2 2\n1 2 3\n");
+        assert!(error_message(load_voxel_text(&path)).contains("expected 8 values, found 3"));
+        let path = temp_file("short.raw", &[0; 10]);
+        assert!(error_message(load_voxel_raw(&path, 2, 1, 1)).contains("expected 8 bytes"));
+        let path = temp_file("zero.txt", b"2 0 2\n");
+        assert!(matches!(load_voxel_text(&path), Err(LoadError::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn rejects_overflowing_sizes() {
+        let huge = usize::MAX / 2;
+        let path = temp_file("huge.txt", format!("{} {} 2\n1\n", huge, huge).as_bytes());
+        assert!(error_message(load_voxel_text(&path)).contains("too large"));
+        let path = temp_file("huge.raw", &[0; 4]);
+        assert!(error_message(load_voxel_raw(&path, huge, 1, 1)).contains("too large"));
+        assert!(error_message(load_voxel_raw(&path, huge, huge, 1)).contains("too large"));
+    }
+}
@@ -1,16 +1,11 @@
-mod basic;
-mod hittable;
-mod constants;
-mod camera;
-mod material;
-
 use std::sync::Arc;
 
-use basic::*;
-use hittable::*;
-use camera::*;
-use crate::constants::{random_double, random_double_range};
-use crate::material::*;
+use ray_tracing::{background, integrator};
+use ray_tracing::basic::*;
+use ray_tracing::hittable::*;
+use ray_tracing::camera::*;
+use ray_tracing::constants::{command_line_option, random_double, random_double_range};
+use ray_tracing::material::*;
 
 fn main() {
     let material_ground = Arc::new(lambertian::lambertian(color(0.5, 0.5, 0.5)));
@@ -49,7 +44,26 @@ fn main() {
     let material3 = Arc::new(metal::metal(color(0.7, 0.6, 0.5), 0.0));
     world.add(Arc::new(sphere(point(4.0, 1.0, 0.0), 1.0, material3)));
 
-    let cam = camera();
+    let mut cam = camera();
+    cam.set_background(Arc::new(background::sky_background()));
+    // `cargo run -- --integrator shading-normals` and the like switch to a diagnostic view.
+    // `--integrator mlt:<seed>` fixes the Metropolis samples, not the random spheres above.
+    if let Some(integrator) = integrator::integrator_from_args() {
+        cam.set_integrator(integrator);
+    }
 
-    render(Arc::new(cam), Arc::new(world));
+    // `--accel list` or `--accel bvh` render without the SAH BVH, for comparison.
+    match command_line_option("--accel").as_deref() {
+        Some("list") => render(Arc::new(cam), Arc::new(world)),
+        Some("bvh") => render(Arc::new(cam), Arc::new(bvh_node(world))),
+        Some("sah") | None => {
+            let world = Arc::new(sah_bvh(world));
+            world.print_statistics();
+            render(Arc::new(cam), world);
+        }
+        Some(name) => {
+            eprintln!("Error: unknown acceleration structure \"{}\", expected list, bvh or sah.", name);
+            std::process::exit(2);
+        }
+    }
 }
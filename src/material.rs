@@ -1,8 +1,11 @@
 pub mod lambertian;
 pub mod metal;
 pub mod dielectics;
+pub mod diffuse_light;
+pub mod isotropic;
+pub mod henyey_greenstein;
 
-use crate::basic::{black, Color, empty_ray, Ray};
+use crate::basic::{black, Color, empty_ray, Point, Ray};
 use crate::hittable::HitRecord;
 
 #[derive(Copy, Clone, Debug)]
@@ -22,4 +25,19 @@ pub fn scatter_record(attenuation: Color, scattered: Ray) -> ScatterRecord { Sca
 
 pub trait Scatter {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord>;
+    /// The BSDF times the cosine term towards `scattered`, paired with the solid angle density of
+    /// scatter() choosing that direction. Specular materials return a zero density: their
+    /// directions cannot be chosen by light sampling.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> (Color, f64) {
+        (black(), 0.0)
+    }
+    /// The color the material reflects or scatters at rec, for diagnostic views. Black for
+    /// materials that only emit.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        black()
+    }
+    /// Light emitted at surface coordinates (u, v) and point p, black for non-emissive materials.
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point) -> Color {
+        black()
+    }
 }
\ No newline at end of file
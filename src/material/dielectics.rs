@@ -1,4 +1,4 @@
-use crate::basic::{dot, Ray, ray, reflect, refract, white};
+use crate::basic::{Color, dot, Ray, ray, reflect, refract, white};
 use crate::constants::random_double;
 use crate::hittable::HitRecord;
 use crate::material::{Scatter, scatter_record, ScatterRecord};
@@ -34,4 +34,5 @@ impl Scatter for Dielectrics {
         };
         Some(scatter_record(white(), ray(rec.p, direction, r_in.time())))
     }
+    fn albedo(&self, _rec: &HitRecord) -> Color { white() }
 }
\ No newline at end of file
@@ -0,0 +1,24 @@
+use std::sync::Arc;
+use crate::basic::{Color, Point, Ray};
+use crate::hittable::HitRecord;
+use crate::material::{Scatter, ScatterRecord};
+use crate::texture::solid_color::solid_color;
+use crate::texture::Texture;
+
+/// A light source: emits `emit` from both sides and scatters nothing.
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture + Sync + Send>
+}
+
+pub fn diffuse_light(emit: Color) -> DiffuseLight { DiffuseLight{ emit: Arc::new(solid_color(emit)) } }
+
+pub fn diffuse_light_texture(emit: Arc<dyn Texture + Sync + Send>) -> DiffuseLight { DiffuseLight{ emit } }
+
+impl Scatter for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+    fn emitted(&self, u: f64, v: f64, p: &Point) -> Color {
+        self.emit.value(u, v, p)
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use crate::basic::{Color, dot, rand_unit_vec, Ray, ray, Vec};
+use crate::constants::{PI, random_double};
+use crate::hittable::HitRecord;
+use crate::material::{Scatter, scatter_record, ScatterRecord};
+use crate::pdf::local_to_world;
+use crate::texture::solid_color::solid_color;
+use crate::texture::Texture;
+
+/// Phase function of a medium with a preferred scattering direction. The anisotropy g is the
+/// mean cosine of the scattering angle: positive values scatter forwards (clouds are around
+/// 0.85), negative values backwards and zero is isotropic.
+pub struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture + Sync + Send>,
+    g: f64
+}
+
+pub fn henyey_greenstein(albedo: Color, g: f64) -> HenyeyGreenstein {
+    HenyeyGreenstein{ albedo: Arc::new(solid_color(albedo)), g: g.clamp(-0.99, 0.99) }
+}
+
+pub fn henyey_greenstein_texture(albedo: Arc<dyn Texture + Sync + Send>, g: f64) -> HenyeyGreenstein {
+    HenyeyGreenstein{ albedo, g: g.clamp(-0.99, 0.99) }
+}
+
+/// Density over the sphere of scattering by an angle whose cosine is cos_theta.
+pub fn hg_phase(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+}
+
+impl HenyeyGreenstein {
+    /// Draw a direction around `forward` (a unit vector) by inverting the phase function's CDF.
+    fn sample(&self, forward: &Vec) -> Vec {
+        let g = self.g;
+        if g.abs() < 1e-3 {
+            return rand_unit_vec();
+        }
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * random_double());
+        let cos_theta = ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * random_double();
+        local_to_world(forward, sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+}
+
+impl Scatter for HenyeyGreenstein {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        let direction = self.sample(&r_in.direction().unit());
+        Some(scatter_record(attenuation, ray(rec.p, direction, r_in.time())))
+    }
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> (Color, f64) {
+        let pdf = hg_phase(dot(&r_in.direction().unit(), &scattered.direction().unit()), self.g);
+        (pdf * self.albedo.value(rec.u, rec.v, &rec.p), pdf)
+    }
+    fn albedo(&self, rec: &HitRecord) -> Color { self.albedo.value(rec.u, rec.v, &rec.p) }
+}
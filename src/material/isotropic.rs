@@ -0,0 +1,28 @@
+use std::sync::Arc;
+use crate::basic::{Color, rand_unit_vec, Ray, ray};
+use crate::constants::PI;
+use crate::hittable::HitRecord;
+use crate::material::{Scatter, scatter_record, ScatterRecord};
+use crate::texture::solid_color::solid_color;
+use crate::texture::Texture;
+
+/// Phase function of a medium scattering equally in all directions.
+pub struct Isotropic {
+    albedo: Arc<dyn Texture + Sync + Send>
+}
+
+pub fn isotropic(albedo: Color) -> Isotropic { Isotropic{ albedo: Arc::new(solid_color(albedo)) } }
+
+pub fn isotropic_texture(albedo: Arc<dyn Texture + Sync + Send>) -> Isotropic { Isotropic{ albedo } }
+
+impl Scatter for Isotropic {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        Some(scatter_record(attenuation, ray(rec.p, rand_unit_vec(), r_in.time())))
+    }
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, _scattered: &Ray) -> (Color, f64) {
+        let pdf = 1.0 / (4.0 * PI);
+        (pdf * self.albedo.value(rec.u, rec.v, &rec.p), pdf)
+    }
+    fn albedo(&self, rec: &HitRecord) -> Color { self.albedo.value(rec.u, rec.v, &rec.p) }
+}
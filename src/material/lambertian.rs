@@ -1,21 +1,30 @@
-use crate::basic::{black, Color, rand_unit_vec, Ray, ray};
+use std::sync::Arc;
+use crate::basic::{Color, Ray, ray};
 use crate::hittable::HitRecord;
 use crate::material::{Scatter, scatter_record, ScatterRecord};
+use crate::pdf::{cosine_pdf, Pdf};
+use crate::texture::solid_color::{empty_solid_color, solid_color};
+use crate::texture::Texture;
 
 pub struct Lambertian {
-    albedo: Color
+    albedo: Arc<dyn Texture + Sync + Send>
 }
 
-pub fn empty_lambertian() -> Lambertian { Lambertian{ albedo: black() } }
+pub fn empty_lambertian() -> Lambertian { Lambertian{ albedo: Arc::new(empty_solid_color()) } }
 
-pub fn lambertian(albedo: Color) -> Lambertian { Lambertian{ albedo } }
+pub fn lambertian(albedo: Color) -> Lambertian { Lambertian{ albedo: Arc::new(solid_color(albedo)) } }
+
+pub fn lambertian_texture(albedo: Arc<dyn Texture + Sync + Send>) -> Lambertian { Lambertian{ albedo } }
 
 impl Scatter for Lambertian {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let mut scatter_direction = rec.normal + rand_unit_vec();
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
-        Some(scatter_record(self.albedo, ray(rec.p, scatter_direction, r_in.time())))
+        let scatter_direction = cosine_pdf(&rec.normal).generate();
+        let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        Some(scatter_record(attenuation, ray(rec.p, scatter_direction, r_in.time())))
+    }
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> (Color, f64) {
+        let pdf = cosine_pdf(&rec.normal).value(scattered.direction());
+        (pdf * self.albedo.value(rec.u, rec.v, &rec.p), pdf)
     }
-}
\ No newline at end of file
+    fn albedo(&self, rec: &HitRecord) -> Color { self.albedo.value(rec.u, rec.v, &rec.p) }
+}
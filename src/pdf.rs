@@ -0,0 +1,34 @@
+pub mod cosine;
+pub mod hittable_pdf;
+pub mod mixture;
+pub mod background_pdf;
+
+pub use cosine::*;
+pub use hittable_pdf::*;
+pub use mixture::*;
+pub use background_pdf::*;
+
+use crate::basic::{cross, Vec, vec};
+
+/// A distribution of directions over the sphere that can be sampled and evaluated.
+pub trait Pdf {
+    /// Solid angle density of generate() producing direction.
+    fn value(&self, direction: &Vec) -> f64;
+    fn generate(&self) -> Vec;
+}
+
+/// Veach's power heuristic (beta = 2): the MIS weight of a sample drawn with density pdf_a when
+/// the other strategy would have drawn it with density pdf_b.
+pub fn power_heuristic(pdf_a: f64, pdf_b: f64) -> f64 {
+    let a = pdf_a * pdf_a;
+    let b = pdf_b * pdf_b;
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+/// Turn coordinates in the frame whose third axis is the unit vector w into a world direction.
+pub fn local_to_world(w: &Vec, x: f64, y: f64, z: f64) -> Vec {
+    let a = if w.x().abs() > 0.9 { vec(0.0, 1.0, 0.0) } else { vec(1.0, 0.0, 0.0) };
+    let v = cross(w, &a).unit();
+    let u = cross(w, &v);
+    x * u + y * v + z * *w
+}
@@ -0,0 +1,16 @@
+use crate::background::Background;
+use crate::basic::{rand_unit_vec, Vec};
+use super::Pdf;
+
+/// The importance sampling of a background. Only meaningful for backgrounds whose
+/// sample_direction() returns Some.
+pub struct BackgroundPdf<'a> {
+    background: &'a (dyn Background + Sync + Send)
+}
+
+pub fn background_pdf(background: &(dyn Background + Sync + Send)) -> BackgroundPdf<'_> { BackgroundPdf { background } }
+
+impl Pdf for BackgroundPdf<'_> {
+    fn value(&self, direction: &Vec) -> f64 { self.background.pdf_value(direction) }
+    fn generate(&self) -> Vec { self.background.sample_direction().unwrap_or_else(rand_unit_vec) }
+}
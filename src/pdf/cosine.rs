@@ -0,0 +1,24 @@
+use crate::basic::{dot, Vec};
+use crate::constants::{PI, random_double};
+use super::{local_to_world, Pdf};
+
+/// Directions around a normal with density cos(theta) / pi, the ideal choice for diffuse surfaces.
+pub struct CosinePdf {
+    w: Vec
+}
+
+pub fn cosine_pdf(normal: &Vec) -> CosinePdf { CosinePdf { w: normal.unit() } }
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec) -> f64 {
+        let cos_theta = dot(&direction.unit(), &self.w);
+        if cos_theta > 0.0 { cos_theta / PI } else { 0.0 }
+    }
+    fn generate(&self) -> Vec {
+        let r1 = random_double();
+        let r2 = random_double();
+        let phi = 2.0 * PI * r1;
+        let z = (1.0 - r2).sqrt();
+        local_to_world(&self.w, phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), z)
+    }
+}
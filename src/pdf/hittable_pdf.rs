@@ -0,0 +1,17 @@
+use crate::basic::{Point, Vec};
+use crate::hittable::Hit;
+use super::Pdf;
+
+/// Directions from origin towards an object as it is at time, usually a light or a list of lights.
+pub struct HittablePdf<'a> {
+    objects: &'a (dyn Hit + Send + Sync),
+    origin: Point,
+    time: f64
+}
+
+pub fn hittable_pdf(objects: &(dyn Hit + Send + Sync), origin: Point, time: f64) -> HittablePdf<'_> { HittablePdf { objects, origin, time } }
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec) -> f64 { self.objects.pdf_value(&self.origin, direction, self.time) }
+    fn generate(&self) -> Vec { self.objects.random(&self.origin, self.time) }
+}
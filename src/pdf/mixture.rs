@@ -0,0 +1,22 @@
+use crate::basic::Vec;
+use crate::constants::random_double;
+use super::Pdf;
+
+/// Picks the first distribution with probability `weight` and the second otherwise.
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+    weight: f64
+}
+
+pub fn mixture_pdf<'a>(first: &'a dyn Pdf, second: &'a dyn Pdf, weight: f64) -> MixturePdf<'a> {
+    MixturePdf { pdfs: [first, second], weight }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec) -> f64 {
+        self.weight * self.pdfs[0].value(direction) + (1.0 - self.weight) * self.pdfs[1].value(direction)
+    }
+    fn generate(&self) -> Vec {
+        if random_double() < self.weight { self.pdfs[0].generate() } else { self.pdfs[1].generate() }
+    }
+}
@@ -0,0 +1,12 @@
+pub mod solid_color;
+pub mod checker;
+pub mod image;
+pub mod perlin;
+pub mod noise;
+
+use crate::basic::{Color, Point};
+
+/// A spatially varying color, looked up with the surface coordinates (u, v) and the hit point p.
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color;
+}
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+use crate::basic::{Color, Point};
+use crate::texture::solid_color::solid_color;
+use crate::texture::Texture;
+
+/// A checker pattern of unit cubes of side `scale` in world space.
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture + Sync + Send>,
+    odd: Arc<dyn Texture + Sync + Send>
+}
+
+pub fn checker_texture(scale: f64, even: Arc<dyn Texture + Sync + Send>, odd: Arc<dyn Texture + Sync + Send>) -> CheckerTexture {
+    CheckerTexture { inv_scale: 1.0 / scale, even, odd }
+}
+
+pub fn checker_colors(scale: f64, even: Color, odd: Color) -> CheckerTexture {
+    checker_texture(scale, Arc::new(solid_color(even)), Arc::new(solid_color(odd)))
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+        if (x + y + z) % 2 == 0 { self.even.value(u, v, p) } else { self.odd.value(u, v, p) }
+    }
+}
+
+/// A checker pattern in texture space with `u_count` by `v_count` squares over [0, 1]^2.
+pub struct UvCheckerTexture {
+    u_count: f64,
+    v_count: f64,
+    even: Arc<dyn Texture + Sync + Send>,
+    odd: Arc<dyn Texture + Sync + Send>
+}
+
+pub fn uv_checker_texture(u_count: f64, v_count: f64, even: Arc<dyn Texture + Sync + Send>, odd: Arc<dyn Texture + Sync + Send>) -> UvCheckerTexture {
+    UvCheckerTexture { u_count, v_count, even, odd }
+}
+
+pub fn uv_checker_colors(u_count: f64, v_count: f64, even: Color, odd: Color) -> UvCheckerTexture {
+    uv_checker_texture(u_count, v_count, Arc::new(solid_color(even)), Arc::new(solid_color(odd)))
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color {
+        let i = (u * self.u_count).floor() as i64;
+        let j = (v * self.v_count).floor() as i64;
+        if (i + j) % 2 == 0 { self.even.value(u, v, p) } else { self.odd.value(u, v, p) }
+    }
+}
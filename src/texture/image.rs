@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use crate::basic::{Color, color, Point};
+use crate::texture::Texture;
+
+/// A linear RGB pixel buffer, stored row by row from the top.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>
+}
+
+pub fn image(width: usize, height: usize, pixels: Vec<Color>) -> Image {
+    assert_eq!(width * height, pixels.len(), "Image size does not match the number of pixels.");
+    Image { width, height, pixels }
+}
+
+impl Image {
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear
+}
+
+/// How texel coordinates outside the image are mapped back into it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror
+}
+
+fn wrap_index(i: i64, n: usize, wrap: Wrap) -> usize {
+    let n = n as i64;
+    let ret = match wrap {
+        Wrap::Repeat => i.rem_euclid(n),
+        Wrap::Clamp => i.clamp(0, n - 1),
+        Wrap::Mirror => {
+            let period = i.rem_euclid(2 * n);
+            if period < n { period } else { 2 * n - 1 - period }
+        }
+    };
+    ret as usize
+}
+
+/// Maps an image over [0, 1]^2 in texture space, with v pointing up.
+pub struct ImageTexture {
+    image: Arc<Image>,
+    filter: Filter,
+    wrap: Wrap
+}
+
+pub fn image_texture(image: Arc<Image>) -> ImageTexture { ImageTexture{ image, filter: Filter::Bilinear, wrap: Wrap::Repeat } }
+
+pub fn image_texture_filtered(image: Arc<Image>, filter: Filter, wrap: Wrap) -> ImageTexture { ImageTexture{ image, filter, wrap } }
+
+impl ImageTexture {
+    fn texel(&self, x: i64, y: i64) -> Color {
+        self.image.pixel(wrap_index(x, self.image.width, self.wrap), wrap_index(y, self.image.height, self.wrap))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point) -> Color {
+        let image = &*self.image;
+        if image.width == 0 || image.height == 0 {
+            return color(0.0, 1.0, 1.0); // Solid cyan as a debugging aid
+        }
+        // Continuous texel coordinates, with texel centers at integer + 0.5.
+        let s = u * image.width as f64;
+        let t = (1.0 - v) * image.height as f64;
+        match self.filter {
+            Filter::Nearest => self.texel(s.floor() as i64, t.floor() as i64),
+            Filter::Bilinear => {
+                let s = s - 0.5;
+                let t = t - 0.5;
+                let x = s.floor();
+                let y = t.floor();
+                let fx = s - x;
+                let fy = t - y;
+                let (x, y) = (x as i64, y as i64);
+                (1.0 - fx) * (1.0 - fy) * self.texel(x, y) + fx * (1.0 - fy) * self.texel(x + 1, y)
+                    + (1.0 - fx) * fy * self.texel(x, y + 1) + fx * fy * self.texel(x + 1, y + 1)
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+use crate::basic::{Color, Point, white};
+use crate::texture::perlin::Perlin;
+use crate::texture::Texture;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NoisePattern {
+    Noise,
+    Fbm,
+    Turbulence,
+    Marble,
+    Wood
+}
+
+/// Grey-scale procedural patterns built from Perlin noise, tinted by `albedo`.
+pub struct NoiseTexture {
+    noise: Arc<Perlin>,
+    pattern: NoisePattern,
+    scale: f64,
+    octaves: i32,
+    albedo: Color
+}
+
+pub fn noise_texture(noise: Arc<Perlin>, pattern: NoisePattern, scale: f64) -> NoiseTexture {
+    NoiseTexture { noise, pattern, scale, octaves: 7, albedo: white() }
+}
+
+pub fn noise_texture_tinted(noise: Arc<Perlin>, pattern: NoisePattern, scale: f64, octaves: i32, albedo: Color) -> NoiseTexture {
+    NoiseTexture { noise, pattern, scale, octaves, albedo }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        let sp = self.scale * *p;
+        let intensity = match self.pattern {
+            NoisePattern::Noise => 0.5 * (1.0 + self.noise.noise(&sp)),
+            NoisePattern::Fbm => 0.5 * (1.0 + self.noise.fbm(&sp, self.octaves, 0.5)),
+            NoisePattern::Turbulence => self.noise.turb(&sp, self.octaves),
+            NoisePattern::Marble => 0.5 * (1.0 + (sp.z() + 10.0 * self.noise.turb(p, self.octaves)).sin()),
+            NoisePattern::Wood => {
+                // Concentric rings around the Y axis, distorted by turbulence.
+                let rings = 10.0 * ((sp.x() * sp.x() + sp.z() * sp.z()).sqrt() + 0.5 * self.noise.turb(&sp, self.octaves));
+                rings - rings.floor()
+            }
+        };
+        intensity.clamp(0.0, 1.0) * self.albedo
+    }
+}
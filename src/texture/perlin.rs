@@ -0,0 +1,103 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use crate::basic::{dot, Point, vec, Vec};
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise on a lattice of random unit vectors. The same seed always yields the same noise.
+pub struct Perlin {
+    rand_vec: [Vec; POINT_COUNT],
+    perm_x: [usize; POINT_COUNT],
+    perm_y: [usize; POINT_COUNT],
+    perm_z: [usize; POINT_COUNT]
+}
+
+pub fn perlin(seed: u64) -> Perlin {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut rand_vec = [vec(0.0, 0.0, 0.0); POINT_COUNT];
+    for v in rand_vec.iter_mut() {
+        // Rejection sampling keeps the gradient directions uniform.
+        *v = loop {
+            let p = vec(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            let len = p.length_squared();
+            if len > 1e-8 && len < 1.0 { break p.unit(); }
+        };
+    }
+    Perlin {
+        rand_vec,
+        perm_x: generate_perm(&mut rng),
+        perm_y: generate_perm(&mut rng),
+        perm_z: generate_perm(&mut rng)
+    }
+}
+
+fn generate_perm(rng: &mut StdRng) -> [usize; POINT_COUNT] {
+    let mut p = [0; POINT_COUNT];
+    for (i, value) in p.iter_mut().enumerate() {
+        *value = i;
+    }
+    p.shuffle(rng);
+    p
+}
+
+/// Hermite smoothstep, so the interpolation has a continuous first derivative at lattice points.
+fn hermite(t: f64) -> f64 { t * t * (3.0 - 2.0 * t) }
+
+impl Perlin {
+    /// Noise value in roughly [-1, 1], trilinearly interpolated from the 8 surrounding lattice gradients.
+    pub fn noise(&self, p: &Point) -> f64 {
+        let u = p.x() - p.x().floor();
+        let v = p.y() - p.y().floor();
+        let w = p.z() - p.z().floor();
+        let i = p.x().floor() as i64;
+        let j = p.y().floor() as i64;
+        let k = p.z().floor() as i64;
+
+        let uu = hermite(u);
+        let vv = hermite(v);
+        let ww = hermite(w);
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = vec(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * dot(&self.rand_vec[index], &weight);
+                }
+            }
+        }
+        accum
+    }
+    /// Fractional Brownian motion: the signed sum of `octaves` noise layers, each at twice the
+    /// frequency (lacunarity) and `gain` times the amplitude of the previous one.
+    pub fn fbm(&self, p: &Point, octaves: i32, gain: f64) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp_p);
+            weight *= gain;
+            temp_p *= 2.0;
+        }
+        accum
+    }
+    /// Turbulence: like fbm but summing absolute values, which gives sharp creases.
+    pub fn turb(&self, p: &Point, octaves: i32) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp_p).abs();
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum
+    }
+}
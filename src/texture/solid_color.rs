@@ -0,0 +1,18 @@
+use crate::basic::{black, Color, color, Point};
+use crate::texture::Texture;
+
+pub struct SolidColor {
+    albedo: Color
+}
+
+pub fn empty_solid_color() -> SolidColor { SolidColor{ albedo: black() } }
+
+pub fn solid_color(albedo: Color) -> SolidColor { SolidColor{ albedo } }
+
+pub fn solid_rgb(r: f64, g: f64, b: f64) -> SolidColor { SolidColor{ albedo: color(r, g, b) } }
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point) -> Color {
+        self.albedo
+    }
+}